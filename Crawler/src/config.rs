@@ -0,0 +1,57 @@
+use crate::normalize::DEFAULT_STRIP_PARAMS;
+
+#[derive(Debug, Clone)]
+pub struct CrawlConfig {
+    // How many links away from a seed URL the crawler is allowed to go
+    pub max_depth: u32,
+    // Upper bound on the number of pages admitted to the frontier in one run
+    pub max_pages: usize,
+    // Only follow links that stay on the same host as the page they were found on
+    pub same_host_only: bool,
+    // Query parameters removed when normalising URLs, `utm_*` style
+    // patterns match on prefix
+    pub strip_params: Vec<String>,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        CrawlConfig {
+            max_depth: 2,
+            max_pages: 1000,
+            same_host_only: false,
+            strip_params: DEFAULT_STRIP_PARAMS.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+}
+
+impl CrawlConfig {
+    pub fn from_env() -> CrawlConfig {
+        let default = CrawlConfig::default();
+        CrawlConfig {
+            max_depth: env_or("CRAWL_MAX_DEPTH", default.max_depth),
+            max_pages: env_or("CRAWL_MAX_PAGES", default.max_pages),
+            same_host_only: env_or("CRAWL_SAME_HOST_ONLY", default.same_host_only),
+            strip_params: match dotenv::var("CRAWL_STRIP_PARAMS") {
+                Ok(val) => val
+                    .split(',')
+                    .map(|p| p.trim().to_ascii_lowercase())
+                    .filter(|p| !p.is_empty())
+                    .collect(),
+                Err(_) => default.strip_params,
+            },
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match dotenv::var(key) {
+        Ok(val) => match val.trim().parse() {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                println!("Invalid value for {}: {:?}, using default", key, val);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
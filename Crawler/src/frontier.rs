@@ -0,0 +1,231 @@
+use std::collections::{HashSet, VecDeque};
+
+use url::Url;
+
+use crate::{config::CrawlConfig, normalize::normalize_str, sitemap::SitemapEntry};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedUrl {
+    pub url: String,
+    pub depth: u32,
+    // Last modification date advertised by a sitemap, if the URL came from one
+    pub lastmod: Option<String>,
+}
+
+// Breadth-first queue of pages waiting to be indexed. URLs are normalised
+// on the way in, every URL is admitted at most once per run and the total
+// number admitted is capped by `max_pages`, so a crawl always terminates.
+pub struct Frontier {
+    config: CrawlConfig,
+    queue: VecDeque<QueuedUrl>,
+    seen: HashSet<String>,
+    admitted: usize,
+}
+
+impl Frontier {
+    pub fn new(config: CrawlConfig) -> Frontier {
+        Frontier {
+            config,
+            queue: VecDeque::new(),
+            seen: HashSet::new(),
+            admitted: 0,
+        }
+    }
+
+    pub fn push_seed(&mut self, url: &str) -> Option<QueuedUrl> {
+        let url = url.trim();
+        if url.is_empty() {
+            return None;
+        }
+        self.admit(url, 0, None)
+    }
+
+    // Queues the links found on a page that sat at `parent_depth`. Returns
+    // the entries that were actually admitted.
+    pub fn push_links(
+        &mut self,
+        parent: &Url,
+        parent_depth: u32,
+        links: Vec<Url>,
+    ) -> Vec<QueuedUrl> {
+        let depth = parent_depth + 1;
+        if depth > self.config.max_depth {
+            return Vec::new();
+        }
+        let mut added = Vec::new();
+        for link in links {
+            if self.config.same_host_only && link.host_str() != parent.host_str() {
+                continue;
+            }
+            if let Some(queued) = self.admit(link.as_str(), depth, None) {
+                added.push(queued);
+            }
+        }
+        added
+    }
+
+    // Queues the entries of a sitemap found at `depth`. They are queued at
+    // the sitemap's own depth, since a sitemap lists pages rather than
+    // linking to them, so a deep sitemap index is still fully expanded.
+    pub fn push_sitemap(
+        &mut self,
+        sitemap: &Url,
+        depth: u32,
+        entries: Vec<SitemapEntry>,
+    ) -> Vec<QueuedUrl> {
+        let mut added = Vec::new();
+        for entry in entries {
+            if self.config.same_host_only && entry.loc.host_str() != sitemap.host_str() {
+                continue;
+            }
+            if let Some(queued) = self.admit(entry.loc.as_str(), depth, entry.lastmod) {
+                added.push(queued);
+            }
+        }
+        added
+    }
+
+    // Puts back an entry that was queued by an earlier run.
+    pub fn requeue(&mut self, entry: QueuedUrl) -> bool {
+        self.admit(&entry.url, entry.depth, entry.lastmod).is_some()
+    }
+
+    // Records a URL that an earlier run already handled so it is never
+    // admitted again. It does not count towards the page budget.
+    pub fn mark_seen(&mut self, url: &str) {
+        let url = self.normalize(url);
+        self.seen.insert(url);
+    }
+
+    // The form a URL takes once admitted. Strings that do not parse as a URL
+    // are kept as they are.
+    pub fn normalize(&self, url: &str) -> String {
+        normalize_str(url, &self.config.strip_params).unwrap_or_else(|| url.trim().to_string())
+    }
+
+    pub fn pop(&mut self) -> Option<QueuedUrl> {
+        self.queue.pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn admitted(&self) -> usize {
+        self.admitted
+    }
+
+    fn admit(&mut self, url: &str, depth: u32, lastmod: Option<String>) -> Option<QueuedUrl> {
+        let url = self.normalize(url);
+        if self.admitted >= self.config.max_pages || self.seen.contains(&url) {
+            return None;
+        }
+        self.seen.insert(url.clone());
+        let queued = QueuedUrl {
+            url,
+            depth,
+            lastmod,
+        };
+        self.queue.push_back(queued.clone());
+        self.admitted += 1;
+        Some(queued)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(list: &[&str]) -> Vec<Url> {
+        list.iter().map(|u| Url::parse(u).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_frontier_depth_and_budget() {
+        let mut frontier = Frontier::new(CrawlConfig {
+            max_depth: 1,
+            max_pages: 3,
+            ..CrawlConfig::default()
+        });
+        assert!(frontier.push_seed("https://example.com/").is_some());
+        assert!(frontier.push_seed("https://EXAMPLE.com/#top").is_none());
+
+        let seed = frontier.pop().unwrap();
+        assert_eq!(seed.depth, 0);
+        let parent = Url::parse(&seed.url).unwrap();
+        let added = frontier.push_links(
+            &parent,
+            seed.depth,
+            urls(&[
+                "https://example.com/a",
+                "https://example.com/b",
+                "https://example.com/c",
+            ]),
+        );
+        // Budget of three pages: the seed plus two links
+        assert_eq!(added.len(), 2);
+        assert_eq!(frontier.admitted(), 3);
+
+        let child = frontier.pop().unwrap();
+        assert_eq!(child.depth, 1);
+        let parent = Url::parse(&child.url).unwrap();
+        assert!(frontier
+            .push_links(&parent, child.depth, urls(&["https://example.com/d"]))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_frontier_same_host_only() {
+        let mut frontier = Frontier::new(CrawlConfig {
+            same_host_only: true,
+            ..CrawlConfig::default()
+        });
+        let parent = Url::parse("https://example.com/").unwrap();
+        let added = frontier.push_links(
+            &parent,
+            0,
+            urls(&["https://example.com/a", "https://other.org/b"]),
+        );
+        assert_eq!(added.len(), 1);
+        assert_eq!(frontier.pop().unwrap().url, "https://example.com/a");
+    }
+
+    #[test]
+    fn test_frontier_resume() {
+        let mut frontier = Frontier::new(CrawlConfig::default());
+        frontier.mark_seen("https://example.com/done");
+        assert!(frontier.requeue(QueuedUrl {
+            url: "https://example.com/queued".to_string(),
+            depth: 1,
+            lastmod: None,
+        }));
+        assert!(frontier.push_seed("https://example.com/done").is_none());
+        assert_eq!(frontier.admitted(), 1);
+        assert_eq!(frontier.pop().unwrap().depth, 1);
+    }
+
+    #[test]
+    fn test_frontier_sitemap_keeps_depth() {
+        let mut frontier = Frontier::new(CrawlConfig {
+            max_depth: 1,
+            ..CrawlConfig::default()
+        });
+        let sitemap = Url::parse("https://example.com/sitemap.xml").unwrap();
+        let added = frontier.push_sitemap(
+            &sitemap,
+            1,
+            vec![SitemapEntry {
+                loc: Url::parse("https://example.com/a").unwrap(),
+                lastmod: Some("2024-05-01".to_string()),
+            }],
+        );
+        assert_eq!(added.len(), 1);
+        let queued = frontier.pop().unwrap();
+        assert_eq!(queued.depth, 1);
+        assert_eq!(queued.lastmod.as_deref(), Some("2024-05-01"));
+    }
+}
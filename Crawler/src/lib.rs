@@ -0,0 +1,11 @@
+mod config;
+mod frontier;
+mod links;
+mod normalize;
+mod sitemap;
+
+pub use config::CrawlConfig;
+pub use frontier::{Frontier, QueuedUrl};
+pub use links::extract_links;
+pub use normalize::{normalize_str, normalize_url, DEFAULT_STRIP_PARAMS};
+pub use sitemap::{decode_sitemap_body, is_sitemap_url, parse_sitemap, Sitemap, SitemapEntry};
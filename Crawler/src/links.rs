@@ -0,0 +1,108 @@
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use url::Url;
+
+// Pulls every <a href> out of a page and resolves it against the page URL
+// (or the document's <base href> if it has one). Only http(s) links are kept
+// and fragments are dropped since they point at the same page.
+pub fn extract_links(html: &str, page_url: &Url) -> Vec<Url> {
+    let mut base_href = None;
+    let mut hrefs = Vec::new();
+
+    let result = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("base[href]", |el| {
+                    if base_href.is_none() {
+                        base_href = el.get_attribute("href");
+                    }
+                    Ok(())
+                }),
+                element!("a[href]", |el| {
+                    let rel = el.get_attribute("rel").unwrap_or_default();
+                    if rel
+                        .split_whitespace()
+                        .any(|r| r.eq_ignore_ascii_case("nofollow"))
+                    {
+                        return Ok(());
+                    }
+                    if let Some(href) = el.get_attribute("href") {
+                        hrefs.push(href);
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::new()
+        },
+    );
+    if let Err(e) = result {
+        println!("Failed to extract links from {}: {}", page_url, e);
+        return Vec::new();
+    }
+
+    let base = match base_href.and_then(|href| page_url.join(href.trim()).ok()) {
+        Some(base) => base,
+        None => page_url.clone(),
+    };
+
+    let mut links: Vec<Url> = Vec::new();
+    for href in hrefs {
+        let href = href.trim();
+        if href.is_empty() || href.starts_with('#') {
+            continue;
+        }
+        let mut link = match base.join(href) {
+            Ok(link) => link,
+            Err(_) => continue,
+        };
+        if link.scheme() != "http" && link.scheme() != "https" {
+            continue;
+        }
+        link.set_fragment(None);
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_links_resolves_relative() {
+        let page = Url::parse("https://example.com/docs/index.html").unwrap();
+        let html = r##"<html><body>
+            <a href="intro.html">Intro</a>
+            <a href="/about#team">About</a>
+            <a href="https://other.org/x">Other</a>
+            <a href="#top">Top</a>
+            <a href="mailto:me@example.com">Mail</a>
+            <a href="/about">About again</a>
+            <a href="/private" rel="nofollow">Private</a>
+        </body></html>"##;
+
+        let links: Vec<String> = extract_links(html, &page)
+            .into_iter()
+            .map(|u| u.to_string())
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                "https://example.com/docs/intro.html",
+                "https://example.com/about",
+                "https://other.org/x",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_links_honours_base() {
+        let page = Url::parse("https://example.com/a/b").unwrap();
+        let html = r#"<html><head><base href="https://cdn.example.com/root/"></head>
+            <body><a href="page">Page</a></body></html>"#;
+        let links = extract_links(html, &page);
+        assert_eq!(links[0].as_str(), "https://cdn.example.com/root/page");
+    }
+}
@@ -0,0 +1,111 @@
+use url::Url;
+
+// Query parameters dropped by default. A trailing `*` matches a prefix.
+pub const DEFAULT_STRIP_PARAMS: &[&str] = &["utm_*", "fbclid", "gclid", "msclkid", "mc_eid"];
+
+// Rewrites a URL into the single form used for deduplication:
+// - scheme and host lowercased, default ports dropped (done by `Url` itself)
+// - fragment removed
+// - trailing slash removed from every path but the root
+// - query parameters matching `strip_params` removed and the rest sorted
+pub fn normalize_url(url: &Url, strip_params: &[String]) -> Url {
+    let mut url = url.clone();
+    url.set_fragment(None);
+
+    if url.cannot_be_a_base() {
+        return url;
+    }
+
+    let path = url.path().to_string();
+    if path.len() > 1 && path.ends_with('/') {
+        url.set_path(path.trim_end_matches('/'));
+        if url.path().is_empty() {
+            url.set_path("/");
+        }
+    }
+
+    if url.query().is_some() {
+        let mut params: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(key, _)| !is_stripped(key, strip_params))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        if params.is_empty() {
+            url.set_query(None);
+        } else {
+            params.sort();
+            url.query_pairs_mut().clear().extend_pairs(params);
+        }
+    }
+    url
+}
+
+// Same as `normalize_url` for a string, returning None when it does not parse
+pub fn normalize_str(url: &str, strip_params: &[String]) -> Option<String> {
+    match Url::parse(url.trim()) {
+        Ok(parsed) => Some(normalize_url(&parsed, strip_params).to_string()),
+        Err(_) => None,
+    }
+}
+
+fn is_stripped(key: &str, strip_params: &[String]) -> bool {
+    let key = key.to_ascii_lowercase();
+    strip_params
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => key.starts_with(prefix),
+            None => key == *pattern,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> Vec<String> {
+        DEFAULT_STRIP_PARAMS.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_variants_collapse() {
+        let strip = defaults();
+        let expected = "https://x.com/a";
+        for variant in [
+            "https://x.com/a",
+            "https://x.com/a/",
+            "https://X.com/a#frag",
+            "HTTPS://x.com:443/a",
+            "https://x.com/a?utm_source=news&utm_medium=email",
+            "https://x.com/a?fbclid=abc",
+        ] {
+            assert_eq!(
+                normalize_str(variant, &strip).unwrap(),
+                expected,
+                "{}",
+                variant
+            );
+        }
+    }
+
+    #[test]
+    fn test_query_sorted_and_root_kept() {
+        let strip = defaults();
+        assert_eq!(
+            normalize_str("http://x.com:8080/?b=2&a=1&utm_campaign=x", &strip).unwrap(),
+            "http://x.com:8080/?a=1&b=2"
+        );
+        assert_eq!(
+            normalize_str("https://x.com", &strip).unwrap(),
+            "https://x.com/"
+        );
+        assert_eq!(normalize_str("not a url", &strip), None);
+    }
+
+    #[test]
+    fn test_stripping_is_configurable() {
+        assert_eq!(
+            normalize_str("https://x.com/a?utm_source=x&ref=y", &["ref".to_string()]).unwrap(),
+            "https://x.com/a?utm_source=x"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use quick_xml::{events::Event, Reader};
+use url::Url;
+
+// The sitemap protocol caps an uncompressed sitemap at 50MB
+const MAX_SITEMAP_SIZE: u64 = 50 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SitemapEntry {
+    pub loc: Url,
+    pub lastmod: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sitemap {
+    // <urlset>: pages to index
+    Urls(Vec<SitemapEntry>),
+    // <sitemapindex>: further sitemaps to expand
+    Index(Vec<SitemapEntry>),
+}
+
+impl Sitemap {
+    pub fn into_entries(self) -> Vec<SitemapEntry> {
+        match self {
+            Sitemap::Urls(entries) | Sitemap::Index(entries) => entries,
+        }
+    }
+}
+
+// Whether a URL is named like a sitemap, so it is worth fetching as one
+// before deciding by content.
+pub fn is_sitemap_url(url: &Url) -> bool {
+    let path = url.path().to_ascii_lowercase();
+    path.ends_with(".xml") || path.ends_with(".xml.gz") || path.contains("sitemap")
+}
+
+// Turns a fetched body into text, inflating it first when it is gzipped.
+// Servers often send `sitemap.xml.gz` without a Content-Encoding header, so
+// the gzip magic bytes are checked rather than trusting the headers.
+pub fn decode_sitemap_body(body: &[u8]) -> Option<String> {
+    if body.starts_with(&[0x1f, 0x8b]) {
+        let mut inflated = String::new();
+        let mut decoder = GzDecoder::new(body).take(MAX_SITEMAP_SIZE);
+        match decoder.read_to_string(&mut inflated) {
+            Ok(_) => Some(inflated),
+            Err(e) => {
+                println!("Failed to inflate sitemap: {}", e);
+                None
+            }
+        }
+    } else {
+        String::from_utf8(body.to_vec()).ok()
+    }
+}
+
+// Parses a sitemap or sitemap index. Returns None when the document is not
+// a sitemap at all, so the caller can treat it as an ordinary page.
+// Relative or unparseable <loc> values are resolved against `sitemap_url`
+// or dropped.
+pub fn parse_sitemap(body: &str, sitemap_url: &Url) -> Option<Sitemap> {
+    let mut reader = Reader::from_str(body);
+    reader.config_mut().trim_text(true);
+
+    let mut is_index = None;
+    let mut entries = Vec::new();
+    let mut loc: Option<String> = None;
+    let mut lastmod: Option<String> = None;
+    // Name of the innermost element whose text we care about
+    let mut current: Option<Vec<u8>> = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let name = e.local_name().as_ref().to_ascii_lowercase();
+                if is_index.is_none() {
+                    match name.as_slice() {
+                        b"urlset" => is_index = Some(false),
+                        b"sitemapindex" => is_index = Some(true),
+                        _ => return None,
+                    }
+                    continue;
+                }
+                match name.as_slice() {
+                    b"url" | b"sitemap" => {
+                        loc = None;
+                        lastmod = None;
+                    }
+                    b"loc" | b"lastmod" => current = Some(name),
+                    _ => {}
+                }
+            }
+            Ok(Event::Text(e)) => {
+                if let Some(name) = &current {
+                    let text = match e.unescape() {
+                        Ok(text) => text.trim().to_string(),
+                        Err(_) => continue,
+                    };
+                    match name.as_slice() {
+                        b"loc" => loc = Some(text),
+                        b"lastmod" => lastmod = Some(text),
+                        _ => {}
+                    }
+                }
+            }
+            Ok(Event::CData(e)) => {
+                if let Some(name) = &current {
+                    let text = String::from_utf8_lossy(&e).trim().to_string();
+                    match name.as_slice() {
+                        b"loc" => loc = Some(text),
+                        b"lastmod" => lastmod = Some(text),
+                        _ => {}
+                    }
+                }
+            }
+            Ok(Event::End(e)) => {
+                let name = e.local_name().as_ref().to_ascii_lowercase();
+                match name.as_slice() {
+                    b"loc" | b"lastmod" => current = None,
+                    b"url" | b"sitemap" => {
+                        if let Some(loc) = loc.take().and_then(|l| sitemap_url.join(&l).ok()) {
+                            if loc.scheme() == "http" || loc.scheme() == "https" {
+                                entries.push(SitemapEntry {
+                                    loc,
+                                    lastmod: lastmod.take(),
+                                });
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                // A self-closing root is an empty but valid sitemap
+                if is_index.is_none() {
+                    return match e.local_name().as_ref().to_ascii_lowercase().as_slice() {
+                        b"urlset" => Some(Sitemap::Urls(Vec::new())),
+                        b"sitemapindex" => Some(Sitemap::Index(Vec::new())),
+                        _ => None,
+                    };
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => {
+                println!("Failed to parse sitemap {}: {}", sitemap_url, e);
+                break;
+            }
+        }
+    }
+
+    match is_index {
+        Some(true) => Some(Sitemap::Index(entries)),
+        Some(false) => Some(Sitemap::Urls(entries)),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_urlset() {
+        let base = Url::parse("https://example.com/sitemap.xml").unwrap();
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url><loc>https://example.com/a?x=1&amp;y=2</loc><lastmod>2024-05-01</lastmod></url>
+              <url><loc> /b </loc></url>
+            </urlset>"#;
+        let sitemap = parse_sitemap(xml, &base).unwrap();
+        assert_eq!(
+            sitemap,
+            Sitemap::Urls(vec![
+                SitemapEntry {
+                    loc: Url::parse("https://example.com/a?x=1&y=2").unwrap(),
+                    lastmod: Some("2024-05-01".to_string()),
+                },
+                SitemapEntry {
+                    loc: Url::parse("https://example.com/b").unwrap(),
+                    lastmod: None,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_gzipped_index() {
+        let base = Url::parse("https://example.com/sitemap_index.xml.gz").unwrap();
+        let xml = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <sitemap><loc>https://example.com/sitemap-1.xml.gz</loc></sitemap>
+            </sitemapindex>"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(xml.as_bytes()).unwrap();
+        let body = decode_sitemap_body(&encoder.finish().unwrap()).unwrap();
+
+        match parse_sitemap(&body, &base).unwrap() {
+            Sitemap::Index(entries) => {
+                assert_eq!(entries.len(), 1);
+                assert_eq!(
+                    entries[0].loc.as_str(),
+                    "https://example.com/sitemap-1.xml.gz"
+                );
+            }
+            other => panic!("expected a sitemap index, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_non_sitemap_xml() {
+        let base = Url::parse("https://example.com/feed.xml").unwrap();
+        assert!(parse_sitemap("<rss><channel></channel></rss>", &base).is_none());
+        assert!(parse_sitemap("<html><body>hi</body></html>", &base).is_none());
+    }
+}
@@ -0,0 +1,118 @@
+use mongodb::{bson::doc, error::Error, results::InsertOneResult, Client, Collection};
+
+use crate::models::{Document, TfIdfScore, Words};
+
+pub const DATABASE: &str = "SearchEngine";
+// Words live in the index segments since schema version 3; the migration
+// drops what is left of this collection
+pub const WORDS: &str = "words";
+pub const DOCUMENTS: &str = "documents";
+pub const TF_IDF_SCORES: &str = "tf_idf_scores";
+pub const METADATA: &str = "metadata";
+
+// Connects to MONGODB_URI, or a local server when it is not set
+pub async fn connect() -> mongodb::Database {
+    let url = match dotenv::var("MONGODB_URI") {
+        Ok(url) => url,
+        Err(_) => "mongodb://localhost:27017".to_string(),
+    };
+
+    let client = match Client::with_uri_str(url).await {
+        Ok(client) => {
+            println!("DB connected succesfully");
+            client
+        }
+        Err(e) => {
+            panic!("{}", e);
+        }
+    };
+    client.database(DATABASE)
+}
+
+// The collections every part of the search engine reads or writes
+pub struct Collections {
+    pub words: Collection<Words>,
+    pub documents: Collection<Document>,
+    pub tf_idf_scores: Collection<TfIdfScore>,
+}
+
+impl Collections {
+    pub fn new(db: &mongodb::Database) -> Collections {
+        Collections {
+            words: db.collection(WORDS),
+            documents: db.collection(DOCUMENTS),
+            tf_idf_scores: db.collection(TF_IDF_SCORES),
+        }
+    }
+
+    pub async fn insert_document(&self, document: Document) -> Result<InsertOneResult, Error> {
+        match self.documents.insert_one(document).await {
+            Ok(res) => {
+                println!("Insertion Successful");
+                Ok(res)
+            }
+            Err(e) => {
+                println!("Document Insertion failed:{}", e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn delete_documents(&self, url: &str) -> Result<(), Error> {
+        let result = self.documents.delete_one(doc! { "url": url }).await;
+
+        match result {
+            Ok(_) => {
+                println!("Document deleted successfully");
+                Ok(())
+            }
+            Err(e) => {
+                println!("Error deleting document: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    // Whether a document was stored under exactly this URL
+    pub async fn url_exists(&self, url: &str) -> Result<bool, Error> {
+        let count = match self.documents.count_documents(doc! { "url": url }).await {
+            Ok(count) => count,
+            Err(e) => {
+                println!("Error checking URL existence: {}", e);
+                return Err(e);
+            }
+        };
+        Ok(count > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn collections() -> Collections {
+        Collections::new(&connect().await)
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        let db = collections().await;
+        db.insert_document(Document {
+            url: "https://docs.rs/url/latest/url/".to_string(),
+            ..Document::default()
+        })
+        .await
+        .unwrap();
+        let url = "https://docs.rs/url/latest/url/";
+        let exists = db.url_exists(url).await.unwrap();
+        assert!(exists);
+        db.delete_documents(url).await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_check_url_dne() {
+        let db = collections().await;
+        let url = "asdsda";
+        let exists = db.url_exists(url).await.unwrap();
+        assert!(!exists);
+    }
+}
@@ -0,0 +1,15 @@
+// Types and storage layout shared by the indexer, the TF-IDF job and
+// anything else that reads the index
+pub mod db;
+pub mod merge;
+pub mod models;
+pub mod postings;
+pub mod schema;
+pub mod segment;
+pub mod tables;
+pub mod text;
+
+// Version of the stored document layout, written on every record. Bump it
+// whenever a change to the types in `models` needs existing data to be
+// rewritten, and add the matching step to the indexer's migrations.
+pub const SCHEMA_VERSION: u32 = 3;
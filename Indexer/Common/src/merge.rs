@@ -0,0 +1,281 @@
+// Combines index segments so that the many small ones each indexer run
+// writes do not pile up. Only neighbouring segments are merged, which keeps
+// the newest copy of a document in the newest segment.
+use std::{fs, ops::Range, path::Path};
+
+use crate::{
+    models::Words,
+    segment::{remove_stale_segments, Index, Segment, SegmentError, SegmentFile, SegmentWriter},
+};
+
+#[derive(Debug, Clone)]
+pub struct MergePolicy {
+    // How many segments of the same size tier are merged at once. Tiers
+    // grow by this factor, so every document is rewritten about once per
+    // tier on its way into the largest segment.
+    pub merge_factor: usize,
+    // Segments where more than this share of documents were replaced or
+    // removed are rewritten on their own to reclaim the space
+    pub max_deleted_ratio: f64,
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        MergePolicy {
+            merge_factor: 10,
+            max_deleted_ratio: 0.3,
+        }
+    }
+}
+
+impl MergePolicy {
+    fn factor(&self) -> usize {
+        self.merge_factor.max(2)
+    }
+
+    // Segments of up to `factor` documents are tier 0, up to `factor`
+    // squared tier 1, and so on
+    fn tier(&self, live_docs: usize) -> u32 {
+        let mut tier = 0;
+        let mut size = live_docs;
+        while size >= self.factor() {
+            size /= self.factor();
+            tier += 1;
+        }
+        tier
+    }
+
+    // The next run of segments to merge, as a range of positions in
+    // `index.segments()`
+    pub fn find_merge(&self, index: &Index) -> Option<Range<usize>> {
+        let segments = index.segments();
+        let live = |i: usize| segments[i].doc_count() as usize - index.deleted_count(i);
+        let factor = self.factor();
+        if segments.len() >= factor {
+            for start in 0..=segments.len() - factor {
+                let tier = self.tier(live(start));
+                if (start + 1..start + factor).all(|i| self.tier(live(i)) == tier) {
+                    return Some(start..start + factor);
+                }
+            }
+        }
+        (0..segments.len()).find_map(|i| {
+            let docs = segments[i].doc_count() as usize;
+            let mostly_deleted =
+                docs > 0 && index.deleted_count(i) as f64 / docs as f64 > self.max_deleted_ratio;
+            // Removals recorded by the oldest segment hide nothing any more
+            let needless_removals = i == 0 && segments[i].removed_count() > 0;
+            (mostly_deleted || needless_removals).then_some(i..i + 1)
+        })
+    }
+}
+
+// Every document of a segment with the postings that mention it
+fn read_documents(segment: &Segment) -> Result<Vec<(u32, Vec<Words>)>, SegmentError> {
+    let mut documents: Vec<(u32, Vec<Words>)> = (0..segment.doc_count())
+        .map(|doc| (doc, Vec::new()))
+        .collect();
+    let ids: Vec<_> = segment
+        .documents()?
+        .into_iter()
+        .map(|stored| stored.id)
+        .collect();
+    for term in segment.terms()? {
+        for posting in segment.postings(&term)? {
+            let doc = posting.doc as usize;
+            let mut words = Words::new(ids[doc], term.clone(), posting.count as i32);
+            words.positions = posting.positions;
+            documents[doc].1.push(words);
+        }
+    }
+    Ok(documents)
+}
+
+// Rewrites the segments in `run` as one, leaving out the documents newer
+// segments replaced or removed. The statistics of the new segment are
+// computed from what is left. The merged file is in place before the old
+// ones are deleted, and covers their generations so that readers skip them
+// even if deleting them fails.
+pub fn merge(index: &Index, run: Range<usize>, dir: &Path) -> Result<(), SegmentError> {
+    let mut writer = SegmentWriter::new();
+    for i in run.clone() {
+        let segment = &index.segments()[i];
+        // With nothing older left, removals have nothing to hide
+        if run.start > 0 {
+            for id in segment.removed()? {
+                writer.remove(id);
+            }
+        }
+        for (doc, words) in read_documents(segment)? {
+            if !index.is_deleted(i, doc) {
+                writer.add_stored(segment.document(doc)?, words);
+            }
+        }
+    }
+
+    let files = &index.files()[run];
+    let first = files
+        .iter()
+        .map(|file| file.first)
+        .min()
+        .unwrap_or_default();
+    let last = files.iter().map(|file| file.last).max().unwrap_or_default();
+    let path = dir.join(SegmentFile::name(first, last));
+    writer.write_to(&path)?;
+    for file in files {
+        if file.path != path {
+            fs::remove_file(&file.path)?;
+        }
+    }
+    println!(
+        "Merged {} segments into {} with {} documents",
+        files.len(),
+        path.display(),
+        writer.len()
+    );
+    Ok(())
+}
+
+// Merges segments in `dir` until `policy` finds nothing more to do,
+// returning how many merges ran
+pub fn compact(dir: &Path, policy: &MergePolicy) -> Result<usize, SegmentError> {
+    remove_stale_segments(dir)?;
+    let mut merges = 0;
+    loop {
+        let index = Index::open(dir)?;
+        match policy.find_merge(&index) {
+            Some(run) => merge(&index, run, dir)?,
+            None => return Ok(merges),
+        }
+        merges += 1;
+    }
+}
+
+// Merges every segment in `dir` into one
+pub fn force_merge(dir: &Path) -> Result<(), SegmentError> {
+    remove_stale_segments(dir)?;
+    let index = Index::open(dir)?;
+    let segments = index.segments();
+    let clean =
+        segments.len() == 1 && index.deleted_count(0) == 0 && segments[0].removed_count() == 0;
+    if segments.is_empty() || clean {
+        return Ok(());
+    }
+    merge(&index, 0..segments.len(), dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{Document, Positions},
+        segment::segment_files,
+    };
+
+    fn add(writer: &mut SegmentWriter, document: &Document, word: &str) {
+        let mut words = Words::new(document.get_id(), word.to_string(), 1);
+        words.positions = Positions {
+            body: vec![0],
+            ..Positions::default()
+        };
+        writer.add(document, vec![words]);
+    }
+
+    fn page(url: &str) -> Document {
+        Document {
+            url: url.to_string(),
+            ..Document::default()
+        }
+    }
+
+    #[test]
+    fn test_force_merge_drops_deleted_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let kept = page("https://example.com/kept");
+        let replaced = page("https://example.com/replaced");
+        let removed = page("https://example.com/removed");
+
+        let mut writer = SegmentWriter::new();
+        add(&mut writer, &kept, "crab");
+        add(&mut writer, &replaced, "crab");
+        add(&mut writer, &removed, "crab");
+        writer.write(dir.path()).unwrap();
+        let mut writer = SegmentWriter::new();
+        add(&mut writer, &replaced, "lobster");
+        writer.remove(removed.get_id());
+        writer.write(dir.path()).unwrap();
+
+        force_merge(dir.path()).unwrap();
+        let files = segment_files(dir.path()).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].path.ends_with("0000000001-0000000002.seg"));
+        let index = Index::open(dir.path()).unwrap();
+        let segment = &index.segments()[0];
+        assert_eq!(segment.doc_count(), 2);
+        assert_eq!(segment.removed_count(), 0);
+        assert_eq!(segment.doc_freq("crab").unwrap(), 1);
+        assert_eq!(segment.doc_freq("lobster").unwrap(), 1);
+        let lobster = &segment.postings("lobster").unwrap()[0];
+        assert_eq!(lobster.positions.body, [0]);
+        assert_eq!(segment.document(lobster.doc).unwrap().id, replaced.get_id());
+
+        // New segments carry on after the merged generations
+        let mut writer = SegmentWriter::new();
+        add(&mut writer, &page("https://example.com/new"), "crab");
+        assert!(writer
+            .write(dir.path())
+            .unwrap()
+            .ends_with("0000000003.seg"));
+    }
+
+    #[test]
+    fn test_compact_merges_a_tier() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = MergePolicy {
+            merge_factor: 3,
+            ..MergePolicy::default()
+        };
+        for i in 0..4 {
+            let mut writer = SegmentWriter::new();
+            add(
+                &mut writer,
+                &page(&format!("https://example.com/{}", i)),
+                "crab",
+            );
+            writer.write(dir.path()).unwrap();
+        }
+        let index = Index::open(dir.path()).unwrap();
+        assert_eq!(policy.find_merge(&index), Some(0..3));
+
+        assert_eq!(compact(dir.path(), &policy).unwrap(), 1);
+        let index = Index::open(dir.path()).unwrap();
+        assert_eq!(index.segments().len(), 2);
+        assert_eq!(index.doc_count(), 4);
+        assert_eq!(policy.find_merge(&index), None);
+    }
+
+    #[test]
+    fn test_left_over_segments_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let removed = page("https://example.com/removed");
+        let mut writer = SegmentWriter::new();
+        add(&mut writer, &removed, "crab");
+        writer.write(dir.path()).unwrap();
+        let mut writer = SegmentWriter::new();
+        writer.remove(removed.get_id());
+        let second = writer.write(dir.path()).unwrap();
+        let first = dir.path().join(SegmentFile::name(1, 1));
+        let copy = dir.path().join("copy");
+        fs::copy(&first, &copy).unwrap();
+
+        force_merge(dir.path()).unwrap();
+        assert!(!second.exists());
+        // As if the merge stopped before deleting what it replaced
+        fs::rename(&copy, &first).unwrap();
+        let index = Index::open(dir.path()).unwrap();
+        assert_eq!(index.segments().len(), 1);
+        assert_eq!(index.doc_count(), 0);
+        assert_eq!(remove_stale_segments(dir.path()).unwrap(), 1);
+        assert!(!first.exists());
+    }
+}
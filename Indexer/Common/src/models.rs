@@ -0,0 +1,297 @@
+use std::fmt;
+
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::SCHEMA_VERSION;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Words {
+    pub _id: ObjectId,
+    pub document: ObjectId,
+    pub word: String,
+    pub count: i32,
+    // Where the word occurs in each field of the document
+    #[serde(default)]
+    pub positions: Positions,
+    // Layout the record was written with. Records from before versioning
+    // read as 0.
+    #[serde(default)]
+    pub schema_version: u32,
+}
+impl Default for Words {
+    fn default() -> Self {
+        Words {
+            _id: ObjectId::new(),
+            document: ObjectId::new(),
+            word: "default".to_string(),
+            count: 0,
+            positions: Positions::default(),
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+}
+
+impl Words {
+    pub fn new(document: ObjectId, word: String, count: i32) -> Self {
+        Words {
+            _id: ObjectId::new(),
+            document,
+            word,
+            count,
+            positions: Positions::default(),
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+}
+
+// The parts of a page that are indexed separately
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Title,
+    Description,
+    Body,
+}
+
+impl Field {
+    pub const ALL: [Field; 3] = [Field::Title, Field::Description, Field::Body];
+}
+
+// Token positions of one word in one document, ascending within each field.
+// Positions count the tokens left after stop words are removed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Positions {
+    #[serde(default)]
+    pub title: Vec<u32>,
+    #[serde(default)]
+    pub description: Vec<u32>,
+    #[serde(default)]
+    pub body: Vec<u32>,
+}
+
+impl Positions {
+    pub fn get(&self, field: Field) -> &[u32] {
+        match field {
+            Field::Title => &self.title,
+            Field::Description => &self.description,
+            Field::Body => &self.body,
+        }
+    }
+
+    pub fn push(&mut self, field: Field, position: u32) {
+        match field {
+            Field::Title => self.title.push(position),
+            Field::Description => self.description.push(position),
+            Field::Body => self.body.push(position),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Document {
+    pub _id: ObjectId,
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    pub canonical_url: String,
+    pub summary_text: String,
+    pub full_text: Vec<String>,
+    // Status of the response the document was built from
+    #[serde(default)]
+    pub http_status: u16,
+    // Where the request ended up after following redirects
+    #[serde(default)]
+    pub final_url: String,
+    // Validators sent back on the next visit to ask only for changes
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    #[serde(default)]
+    pub fetched_at: Option<DateTime>,
+    // Hash of the extracted content, to tell real changes from a new ETag
+    #[serde(default)]
+    pub content_hash: String,
+    // Revisit bookkeeping kept by the scheduler
+    #[serde(default)]
+    pub fetch_count: u32,
+    #[serde(default)]
+    pub change_count: u32,
+    #[serde(default)]
+    pub revisit_secs: i64,
+    #[serde(default)]
+    pub next_fetch_at: Option<DateTime>,
+    // Set while the page's words are not in a segment on disk yet. Pages
+    // still unindexed when the indexer starts are indexed again.
+    #[serde(default)]
+    pub unindexed: bool,
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Document {
+            _id: ObjectId::new(),
+            url: "Testing Test ting 124".to_string(),
+            title: "Testing Title".to_string(),
+            description: "Testing Description".to_string(),
+            canonical_url: "https://example.com/testing".to_string(),
+            summary_text: "Testing Summary".to_string(),
+            full_text: vec!["Testing full text".to_string()],
+            http_status: 200,
+            final_url: String::new(),
+            etag: None,
+            last_modified: None,
+            fetched_at: None,
+            content_hash: String::new(),
+            fetch_count: 0,
+            change_count: 0,
+            revisit_secs: 0,
+            next_fetch_at: None,
+            unindexed: false,
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+}
+impl Document {
+    pub fn new(
+        url: String,
+        title: String,
+        description: String,
+        canonical_url: String,
+        summary_text: String,
+        full_text: Vec<String>,
+    ) -> Self {
+        Document {
+            _id: ObjectId::new(),
+            url,
+            title,
+            description,
+            canonical_url,
+            summary_text,
+            full_text,
+            http_status: 200,
+            final_url: String::new(),
+            etag: None,
+            last_modified: None,
+            fetched_at: None,
+            content_hash: String::new(),
+            fetch_count: 0,
+            change_count: 0,
+            revisit_secs: 0,
+            next_fetch_at: None,
+            unindexed: false,
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+    pub fn get_full_text(&self) -> &Vec<String> {
+        &self.full_text
+    }
+    pub fn get_title(&self) -> String {
+        self.title.clone()
+    }
+    pub fn get_description(&self) -> String {
+        self.description.clone()
+    }
+    pub fn get_id(&self) -> ObjectId {
+        self._id
+    }
+}
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Document {{")?;
+        writeln!(f, "  title: {:?}", self.title)?;
+        writeln!(f, "  description: {:?}", self.description)?;
+        writeln!(f, "  canonical_url: {:?}", self.canonical_url)?;
+        writeln!(f, "  summary_text: \"{}\"", self.summary_text)?;
+        writeln!(f, "  full_text: {:?} words", self.full_text.len())?;
+        write!(f, "}}")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TfIdfScore {
+    pub _id: ObjectId,
+    pub word: String,
+    pub document_id: ObjectId,
+    pub url: String,
+    pub tf: f64,     // Term frequency
+    pub idf: f64,    // Inverse document frequency
+    pub tf_idf: f64, // TF-IDF score
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+impl TfIdfScore {
+    pub fn new(word: String, document_id: ObjectId, url: String, tf: f64, idf: f64) -> Self {
+        let tf_idf = tf * idf;
+        TfIdfScore {
+            _id: ObjectId::new(),
+            word,
+            document_id,
+            url,
+            tf,
+            idf,
+            tf_idf,
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+}
+
+impl Default for TfIdfScore {
+    fn default() -> Self {
+        TfIdfScore {
+            _id: ObjectId::new(),
+            word: "default".to_string(),
+            document_id: ObjectId::new(),
+            url: "default".to_string(),
+            tf: 0.0,
+            idf: 0.0,
+            tf_idf: 0.0,
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DocumentMetadata {
+    pub _id: ObjectId,
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    // Exclude large fields: canonical_url, summary_text, full_text
+}
+
+impl DocumentMetadata {
+    pub fn new(_id: ObjectId, url: String, title: String, description: String) -> Self {
+        DocumentMetadata {
+            _id,
+            url,
+            title,
+            description,
+        }
+    }
+}
+
+// The one record of the metadata collection, tracking which schema version
+// the stored data as a whole has been migrated to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Metadata {
+    pub _id: String,
+    pub schema_version: u32,
+    pub updated_at: DateTime,
+}
+
+impl Metadata {
+    pub const ID: &'static str = "schema";
+
+    pub fn new(schema_version: u32) -> Self {
+        Metadata {
+            _id: Metadata::ID.to_string(),
+            schema_version,
+            updated_at: DateTime::now(),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+// Matching over the token positions stored with each word, for phrase and
+// proximity queries. Every position list must be sorted ascending.
+
+// Whether the terms occur one right after another, given the positions of
+// each term of the phrase in order
+pub fn contains_phrase(terms: &[&[u32]]) -> bool {
+    let (first, rest) = match terms.split_first() {
+        Some(split) => split,
+        None => return false,
+    };
+    first.iter().any(|&start| {
+        rest.iter()
+            .zip(1..)
+            .all(|(positions, offset)| positions.binary_search(&(start + offset)).is_ok())
+    })
+}
+
+// Whether two terms occur within `distance` tokens of each other, in either
+// order
+pub fn within(a: &[u32], b: &[u32], distance: u32) -> bool {
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].abs_diff(b[j]) <= distance {
+            return true;
+        }
+        // Only moving past the smaller position can bring the two closer
+        if a[i] < b[j] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains_phrase() {
+        // "rust search engine rust engine"
+        let rust = [0, 3];
+        let search = [1];
+        let engine = [2, 4];
+        assert!(contains_phrase(&[&rust, &search, &engine]));
+        assert!(contains_phrase(&[&rust, &engine]));
+        assert!(!contains_phrase(&[&search, &rust]));
+        assert!(!contains_phrase(&[&engine, &search]));
+        assert!(contains_phrase(&[&search]));
+        assert!(!contains_phrase(&[]));
+    }
+
+    #[test]
+    fn test_within() {
+        let a = [1, 20];
+        let b = [5, 40];
+        assert!(within(&a, &b, 4));
+        assert!(!within(&a, &b, 3));
+        assert!(within(&b, &a, 4));
+        assert!(!within(&a, &[], 100));
+    }
+}
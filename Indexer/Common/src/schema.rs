@@ -0,0 +1,193 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    error::Error,
+    options::IndexOptions,
+    IndexModel,
+};
+
+use crate::db::{DOCUMENTS, TF_IDF_SCORES};
+
+// An index some part of the search engine relies on
+#[derive(Debug, Clone)]
+pub struct IndexSpec {
+    pub collection: &'static str,
+    pub keys: Document,
+    pub unique: bool,
+}
+
+impl IndexSpec {
+    pub fn new(collection: &'static str, keys: Document) -> IndexSpec {
+        IndexSpec {
+            collection,
+            keys,
+            unique: false,
+        }
+    }
+
+    pub fn unique(collection: &'static str, keys: Document) -> IndexSpec {
+        IndexSpec {
+            unique: true,
+            ..IndexSpec::new(collection, keys)
+        }
+    }
+
+    // The name MongoDB gives the index by default, e.g. `word_1_tf_idf_-1`
+    pub fn name(&self) -> String {
+        self.keys
+            .iter()
+            .map(|(field, order)| format!("{}_{}", field, order))
+            .collect::<Vec<_>>()
+            .join("_")
+    }
+
+    fn model(&self) -> IndexModel {
+        IndexModel::builder()
+            .keys(self.keys.clone())
+            .options(IndexOptions::builder().unique(self.unique).build())
+            .build()
+    }
+}
+
+// Indexes on the collections every crate shares
+pub fn shared_indexes() -> Vec<IndexSpec> {
+    vec![
+        // `url_exists` runs for every URL the crawler sees
+        IndexSpec::unique(DOCUMENTS, doc! { "url": 1 }),
+        // Redirect targets and canonical URLs are matched the same way
+        IndexSpec::new(DOCUMENTS, doc! { "final_url": 1 }),
+        IndexSpec::new(DOCUMENTS, doc! { "canonical_url": 1 }),
+        // Migrations look for records written with an older layout
+        IndexSpec::new(DOCUMENTS, doc! { "schema_version": 1 }),
+        // Best scoring documents for a word first
+        IndexSpec::new(TF_IDF_SCORES, doc! { "word": 1, "tf_idf": -1 }),
+    ]
+}
+
+// JSON schema validators for the shared collections. Only the fields every
+// writer has always set are required, so older records stay valid.
+pub fn validators() -> Vec<(&'static str, Document)> {
+    vec![
+        (
+            DOCUMENTS,
+            doc! {
+                "bsonType": "object",
+                "required": ["url", "title", "description", "canonical_url", "summary_text", "full_text"],
+                "properties": {
+                    "url": { "bsonType": "string", "minLength": 1 },
+                    "title": { "bsonType": "string" },
+                    "description": { "bsonType": "string" },
+                    "canonical_url": { "bsonType": "string" },
+                    "summary_text": { "bsonType": "string" },
+                    "full_text": { "bsonType": "array", "items": { "bsonType": "string" } },
+                    "final_url": { "bsonType": "string" },
+                    "content_hash": { "bsonType": "string" },
+                    "schema_version": { "bsonType": ["int", "long"] },
+                },
+            },
+        ),
+        (
+            TF_IDF_SCORES,
+            doc! {
+                "bsonType": "object",
+                "required": ["word", "document_id", "url", "tf", "idf", "tf_idf"],
+                "properties": {
+                    "word": { "bsonType": "string" },
+                    "document_id": { "bsonType": "objectId" },
+                    "url": { "bsonType": "string" },
+                    "tf": { "bsonType": "double" },
+                    "idf": { "bsonType": "double" },
+                    "tf_idf": { "bsonType": "double" },
+                },
+            },
+        ),
+    ]
+}
+
+// Attaches the validators, creating collections that do not exist yet.
+// Validation is moderate: existing records that do not match are left alone
+// until they are next written.
+pub async fn apply_validators(db: &mongodb::Database) -> Result<(), Error> {
+    let existing = db.list_collection_names().await?;
+    for (collection, schema) in validators() {
+        let action = if existing.iter().any(|name| name == collection) {
+            "collMod"
+        } else {
+            "create"
+        };
+        let mut command = doc! { action: collection };
+        command.insert("validator", doc! { "$jsonSchema": schema });
+        command.insert("validationLevel", "moderate");
+        command.insert("validationAction", "error");
+        db.run_command(command).await?;
+    }
+    Ok(())
+}
+
+// Creates any of `indexes` that are missing. Creating an index that already
+// exists with the same options does nothing.
+pub async fn ensure_indexes(db: &mongodb::Database, indexes: &[IndexSpec]) -> Result<(), Error> {
+    for index in indexes {
+        db.collection::<Document>(index.collection)
+            .create_index(index.model())
+            .await?;
+    }
+    Ok(())
+}
+
+// Validators and indexes, run on startup. Failures are reported but do not
+// stop the caller since everything still works, only slower.
+pub async fn setup(db: &mongodb::Database, indexes: &[IndexSpec]) {
+    if let Err(e) = apply_validators(db).await {
+        println!("Failed to apply collection validators: {}", e);
+    }
+    if let Err(e) = ensure_indexes(db, indexes).await {
+        println!("Failed to create indexes: {}", e);
+    }
+}
+
+// Which of `indexes` exist on the server
+pub async fn index_status(
+    db: &mongodb::Database,
+    indexes: &[IndexSpec],
+) -> Result<Vec<(IndexSpec, bool)>, Error> {
+    let mut status = Vec::new();
+    for index in indexes {
+        let collection = db.collection::<Document>(index.collection);
+        let existing: Vec<IndexModel> = collection.list_indexes().await?.try_collect().await?;
+        let present = existing.iter().any(|model| {
+            let unique = model
+                .options
+                .as_ref()
+                .and_then(|options| options.unique)
+                .unwrap_or(false);
+            model.keys == index.keys && unique == index.unique
+        });
+        status.push((index.clone(), present));
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_names() {
+        let names: Vec<String> = shared_indexes().iter().map(IndexSpec::name).collect();
+        assert!(names.contains(&"url_1".to_string()));
+        assert!(names.contains(&"word_1_tf_idf_-1".to_string()));
+        assert!(shared_indexes()
+            .iter()
+            .any(|index| index.collection == DOCUMENTS && index.unique));
+    }
+
+    #[test]
+    fn test_validators_cover_shared_collections() {
+        let collections: Vec<&str> = validators().iter().map(|(name, _)| *name).collect();
+        assert_eq!(collections, [DOCUMENTS, TF_IDF_SCORES]);
+        for (_, schema) in validators() {
+            assert!(schema.get_array("required").is_ok());
+        }
+    }
+}
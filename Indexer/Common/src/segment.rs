@@ -0,0 +1,885 @@
+// Immutable on-disk index segments. A segment holds the postings of a batch
+// of documents in one file, laid out as
+//
+//   header | postings | term dictionary | stored fields | removed ids | crc32
+//
+// All integers in the header are little endian. Postings lists are delta
+// encoded varints and the term dictionary is an FST from each term to the
+// offset of its postings list, so readers only touch what a query needs.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use fst::{Map, MapBuilder, Streamer};
+use memmap2::Mmap;
+use mongodb::bson::oid::ObjectId;
+
+use crate::models::{Document, Field, Positions, Words};
+
+const MAGIC: &[u8; 8] = b"SEGMENT\0";
+// Bump whenever the layout changes; readers refuse versions they do not know
+pub const FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 8 + 4 * 4 + 3 * 8 + 5 * 8;
+const CHECKSUM_LEN: usize = 4;
+pub const EXTENSION: &str = "seg";
+
+// Where the indexer writes segments and everything else reads them from
+pub fn index_dir() -> PathBuf {
+    PathBuf::from(dotenv::var("INDEX_DIR").unwrap_or_else(|_| "../index".to_string()))
+}
+
+#[derive(Debug)]
+pub enum SegmentError {
+    Io(io::Error),
+    // The file is not a segment, or it was cut short or damaged
+    Corrupt(String),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SegmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SegmentError::Io(e) => write!(f, "{}", e),
+            SegmentError::Corrupt(reason) => write!(f, "corrupt segment: {}", reason),
+            SegmentError::UnsupportedVersion(version) => {
+                write!(f, "unsupported segment format version {}", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SegmentError {}
+
+impl From<io::Error> for SegmentError {
+    fn from(e: io::Error) -> Self {
+        SegmentError::Io(e)
+    }
+}
+
+impl From<fst::Error> for SegmentError {
+    fn from(e: fst::Error) -> Self {
+        SegmentError::Corrupt(e.to_string())
+    }
+}
+
+fn corrupt(reason: &str) -> SegmentError {
+    SegmentError::Corrupt(reason.to_string())
+}
+
+// What a segment keeps of each document besides its postings
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredDocument {
+    pub id: ObjectId,
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    // Number of tokens in each field, in `Field::ALL` order
+    pub lengths: [u32; 3],
+}
+
+impl StoredDocument {
+    pub fn length(&self, field: Field) -> u32 {
+        self.lengths[field as usize]
+    }
+}
+
+// One document's entry in the postings list of a term
+#[derive(Debug, Clone, PartialEq)]
+pub struct Posting {
+    // Position of the document within its segment
+    pub doc: u32,
+    pub count: u32,
+    pub positions: Positions,
+}
+
+// A segment file and the generations it covers. Every flush writes a new
+// generation, named e.g. `0000000007.seg`; a merge of generations 3 to 7 is
+// named `0000000003-0000000007.seg` and replaces every file within that
+// range, so files a merge left behind are never read.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SegmentFile {
+    pub last: u64,
+    pub first: u64,
+    pub path: PathBuf,
+}
+
+impl SegmentFile {
+    fn parse(path: PathBuf) -> Option<SegmentFile> {
+        if path.extension()? != EXTENSION {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        let (first, last) = match stem.split_once('-') {
+            Some((first, last)) => (first.parse().ok()?, last.parse().ok()?),
+            None => (stem.parse().ok()?, stem.parse().ok()?),
+        };
+        (first <= last).then_some(SegmentFile { last, first, path })
+    }
+
+    pub fn name(first: u64, last: u64) -> String {
+        if first == last {
+            format!("{:010}.{}", last, EXTENSION)
+        } else {
+            format!("{:010}-{:010}.{}", first, last, EXTENSION)
+        }
+    }
+
+    fn covers(&self, other: &SegmentFile) -> bool {
+        self.first <= other.first && other.last <= self.last && self != other
+    }
+}
+
+// Segment files in `dir` split into the ones to read, oldest first, and the
+// ones a merge replaced. A directory that does not exist yet holds none.
+fn list_segments(dir: &Path) -> io::Result<(Vec<SegmentFile>, Vec<SegmentFile>)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Default::default()),
+        Err(e) => return Err(e),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        if let Some(file) = SegmentFile::parse(entry?.path()) {
+            files.push(file);
+        }
+    }
+    files.sort();
+    let (stale, live): (Vec<SegmentFile>, Vec<SegmentFile>) = files
+        .iter()
+        .cloned()
+        .partition(|file| files.iter().any(|other| other.covers(file)));
+    Ok((live, stale))
+}
+
+pub fn segment_files(dir: &Path) -> io::Result<Vec<SegmentFile>> {
+    Ok(list_segments(dir)?.0)
+}
+
+// Deletes files a merge replaced but did not get to delete, e.g. because
+// the process stopped half way
+pub fn remove_stale_segments(dir: &Path) -> io::Result<usize> {
+    let stale = list_segments(dir)?.1;
+    for file in &stale {
+        fs::remove_file(&file.path)?;
+    }
+    Ok(stale.len())
+}
+
+// Where the next segment written to `dir` goes
+pub fn next_segment_path(dir: &Path) -> io::Result<PathBuf> {
+    let (live, stale) = list_segments(dir)?;
+    let last = live.iter().chain(&stale).map(|file| file.last).max();
+    let next = last.unwrap_or(0) + 1;
+    Ok(dir.join(SegmentFile::name(next, next)))
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    write_varint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+// Positions are ascending, so only the gaps between them are written
+fn write_positions(out: &mut Vec<u8>, positions: &[u32]) {
+    write_varint(out, positions.len() as u64);
+    let mut last = 0;
+    for &position in positions {
+        write_varint(out, u64::from(position - last));
+        last = position;
+    }
+}
+
+// Collects documents and writes them out as one segment
+#[derive(Default)]
+pub struct SegmentWriter {
+    // Adding a document again replaces what was added before
+    documents: BTreeMap<ObjectId, (StoredDocument, Vec<Words>)>,
+    // Documents to hide from older segments
+    removed: BTreeSet<ObjectId>,
+}
+
+impl SegmentWriter {
+    pub fn new() -> SegmentWriter {
+        SegmentWriter::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    // Ids of the documents added, not counting removals
+    pub fn ids(&self) -> Vec<ObjectId> {
+        self.documents.keys().copied().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn add(&mut self, document: &Document, words: Vec<Words>) {
+        let mut lengths = [0; 3];
+        for word in &words {
+            for field in Field::ALL {
+                if let Some(last) = word.positions.get(field).last() {
+                    lengths[field as usize] = lengths[field as usize].max(last + 1);
+                }
+            }
+        }
+        let stored = StoredDocument {
+            id: document.get_id(),
+            url: document.url.clone(),
+            title: document.title.clone(),
+            description: document.description.clone(),
+            lengths,
+        };
+        self.documents.insert(stored.id, (stored, words));
+    }
+
+    // Adds a document as read back from another segment
+    pub fn add_stored(&mut self, stored: StoredDocument, words: Vec<Words>) {
+        self.documents.insert(stored.id, (stored, words));
+    }
+
+    pub fn remove(&mut self, id: ObjectId) {
+        self.documents.remove(&id);
+        self.removed.insert(id);
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, SegmentError> {
+        let documents: Vec<&(StoredDocument, Vec<Words>)> = self.documents.values().collect();
+        let mut postings: BTreeMap<&str, Vec<(u32, &Words)>> = BTreeMap::new();
+        for (doc, (_, words)) in documents.iter().enumerate() {
+            for word in words {
+                postings
+                    .entry(word.word.as_str())
+                    .or_default()
+                    .push((doc as u32, word));
+            }
+        }
+
+        let mut out = vec![0; HEADER_LEN];
+        let postings_at = out.len();
+        let mut dictionary = MapBuilder::memory();
+        for (term, list) in &postings {
+            dictionary.insert(term, out.len() as u64)?;
+            write_varint(&mut out, list.len() as u64);
+            let mut last = 0;
+            for (doc, word) in list {
+                write_varint(&mut out, u64::from(doc - last));
+                last = *doc;
+                write_varint(&mut out, word.count.max(0) as u64);
+                for field in Field::ALL {
+                    write_positions(&mut out, word.positions.get(field));
+                }
+            }
+        }
+
+        let dictionary_at = out.len();
+        out.extend(dictionary.into_inner()?);
+
+        // An offset per document first, so any one can be read directly
+        let stored_at = out.len();
+        let mut records = Vec::new();
+        let mut total_lengths = [0u64; 3];
+        for (stored, _) in &documents {
+            let offset = stored_at + documents.len() * 8 + records.len();
+            out.extend((offset as u64).to_le_bytes());
+            records.extend(stored.id.bytes());
+            for (total, length) in total_lengths.iter_mut().zip(stored.lengths) {
+                write_varint(&mut records, u64::from(length));
+                *total += u64::from(length);
+            }
+            write_string(&mut records, &stored.url);
+            write_string(&mut records, &stored.title);
+            write_string(&mut records, &stored.description);
+        }
+        out.extend(records);
+
+        let removed_at = out.len();
+        for id in &self.removed {
+            out.extend(id.bytes());
+        }
+        let end = out.len();
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend(MAGIC);
+        for value in [
+            FORMAT_VERSION,
+            documents.len() as u32,
+            postings.len() as u32,
+            self.removed.len() as u32,
+        ] {
+            header.extend(value.to_le_bytes());
+        }
+        for value in total_lengths {
+            header.extend(value.to_le_bytes());
+        }
+        for offset in [postings_at, dictionary_at, stored_at, removed_at, end] {
+            header.extend((offset as u64).to_le_bytes());
+        }
+        out[..HEADER_LEN].copy_from_slice(&header);
+
+        let checksum = crc32fast::hash(&out);
+        out.extend(checksum.to_le_bytes());
+        Ok(out)
+    }
+
+    // Writes the segment to `path`. It is written next to it first and
+    // renamed into place, so readers never see a partial segment.
+    pub fn write_to(&self, path: &Path) -> Result<(), SegmentError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let partial = path.with_extension("partial");
+        let mut file = fs::File::create(&partial)?;
+        file.write_all(&self.encode()?)?;
+        file.sync_all()?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    // Writes the segment as the newest one in `dir`
+    pub fn write(&self, dir: &Path) -> Result<PathBuf, SegmentError> {
+        let path = next_segment_path(dir)?;
+        self.write_to(&path)?;
+        Ok(path)
+    }
+}
+
+// Reads values back out of a segment
+struct Cursor<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8], at: usize) -> Cursor<'a> {
+        Cursor { data, at }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SegmentError> {
+        let end = self
+            .at
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| corrupt("read past the end of the file"))?;
+        let bytes = &self.data[self.at..end];
+        self.at = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, SegmentError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, SegmentError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn varint(&mut self) -> Result<u64, SegmentError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(corrupt("varint is too long"))
+    }
+
+    fn varint_u32(&mut self) -> Result<u32, SegmentError> {
+        u32::try_from(self.varint()?).map_err(|_| corrupt("value out of range"))
+    }
+
+    fn string(&mut self) -> Result<String, SegmentError> {
+        let len = self.varint()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| corrupt("invalid utf-8 in stored field"))
+    }
+
+    fn object_id(&mut self) -> Result<ObjectId, SegmentError> {
+        let mut bytes = [0; 12];
+        bytes.copy_from_slice(self.take(12)?);
+        Ok(ObjectId::from_bytes(bytes))
+    }
+
+    fn positions(&mut self) -> Result<Vec<u32>, SegmentError> {
+        let len = self.varint()? as usize;
+        let mut positions = Vec::with_capacity(len.min(self.data.len()));
+        let mut last: u32 = 0;
+        for _ in 0..len {
+            last = last
+                .checked_add(self.varint_u32()?)
+                .ok_or_else(|| corrupt("position out of range"))?;
+            positions.push(last);
+        }
+        Ok(positions)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    doc_count: u32,
+    term_count: u32,
+    removed_count: u32,
+    // Sum of each field's length over every document
+    total_lengths: [u64; 3],
+    postings: usize,
+    dictionary: usize,
+    stored: usize,
+    removed: usize,
+    end: usize,
+}
+
+impl Header {
+    fn read(data: &[u8]) -> Result<Header, SegmentError> {
+        if data.len() < HEADER_LEN + CHECKSUM_LEN || &data[..MAGIC.len()] != MAGIC {
+            return Err(corrupt("not a segment file"));
+        }
+        let mut cursor = Cursor::new(data, MAGIC.len());
+        let version = cursor.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(SegmentError::UnsupportedVersion(version));
+        }
+        let doc_count = cursor.u32()?;
+        let term_count = cursor.u32()?;
+        let removed_count = cursor.u32()?;
+        let mut total_lengths = [0; 3];
+        for total in &mut total_lengths {
+            *total = cursor.u64()?;
+        }
+        let mut offsets = [0; 5];
+        for offset in &mut offsets {
+            *offset = cursor.u64()? as usize;
+        }
+        let [postings, dictionary, stored, removed, end] = offsets;
+
+        if end != data.len() - CHECKSUM_LEN
+            || !(HEADER_LEN <= postings
+                && postings <= dictionary
+                && dictionary <= stored
+                && stored <= removed
+                && removed <= end)
+            || removed + removed_count as usize * 12 != end
+            || stored + doc_count as usize * 8 > removed
+        {
+            return Err(corrupt("section offsets out of bounds"));
+        }
+        Ok(Header {
+            doc_count,
+            term_count,
+            removed_count,
+            total_lengths,
+            postings,
+            dictionary,
+            stored,
+            removed,
+            end,
+        })
+    }
+}
+
+// A segment file mapped into memory
+pub struct Segment {
+    path: PathBuf,
+    data: Mmap,
+    header: Header,
+}
+
+impl Segment {
+    // Maps the segment at `path` and checks its header and checksum
+    pub fn open(path: &Path) -> Result<Segment, SegmentError> {
+        let file = fs::File::open(path)?;
+        // SAFETY: segments are never modified once they are renamed into
+        // place; merges write new files and unlink the old ones.
+        let data = unsafe { Mmap::map(&file)? };
+        let header = Header::read(&data)?;
+        let (body, checksum) = data.split_at(header.end);
+        if crc32fast::hash(body).to_le_bytes() != checksum {
+            return Err(corrupt("checksum mismatch"));
+        }
+        Ok(Segment {
+            path: path.to_path_buf(),
+            data,
+            header,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn doc_count(&self) -> u32 {
+        self.header.doc_count
+    }
+
+    pub fn term_count(&self) -> u32 {
+        self.header.term_count
+    }
+
+    pub fn removed_count(&self) -> u32 {
+        self.header.removed_count
+    }
+
+    // Size of the file on disk
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    // Average number of tokens in `field` over the segment's documents
+    pub fn average_length(&self, field: Field) -> f64 {
+        if self.header.doc_count == 0 {
+            return 0.0;
+        }
+        self.header.total_lengths[field as usize] as f64 / self.header.doc_count as f64
+    }
+
+    fn dictionary(&self) -> Result<Map<&[u8]>, SegmentError> {
+        let bytes = &self.data[self.header.dictionary..self.header.stored];
+        Ok(Map::new(bytes)?)
+    }
+
+    fn cursor(&self, at: usize) -> Cursor<'_> {
+        Cursor::new(&self.data[..self.header.end], at)
+    }
+
+    // Every term in the segment, in byte order
+    pub fn terms(&self) -> Result<Vec<String>, SegmentError> {
+        let dictionary = self.dictionary()?;
+        let mut terms = Vec::with_capacity(self.header.term_count as usize);
+        let mut stream = dictionary.stream();
+        while let Some((term, _)) = stream.next() {
+            terms.push(String::from_utf8_lossy(term).into_owned());
+        }
+        Ok(terms)
+    }
+
+    // How many documents of the segment contain `term`
+    pub fn doc_freq(&self, term: &str) -> Result<u32, SegmentError> {
+        match self.dictionary()?.get(term) {
+            Some(offset) => self.cursor(offset as usize).varint_u32(),
+            None => Ok(0),
+        }
+    }
+
+    // The documents containing `term`, in segment order
+    pub fn postings(&self, term: &str) -> Result<Vec<Posting>, SegmentError> {
+        let offset = match self.dictionary()?.get(term) {
+            Some(offset) => offset as usize,
+            None => return Ok(Vec::new()),
+        };
+        if offset < self.header.postings || offset >= self.header.dictionary {
+            return Err(corrupt("postings offset out of bounds"));
+        }
+        let mut cursor = self.cursor(offset);
+        let len = cursor.varint_u32()?;
+        let mut postings = Vec::with_capacity(len.min(self.header.doc_count) as usize);
+        let mut doc: u32 = 0;
+        for _ in 0..len {
+            doc = doc
+                .checked_add(cursor.varint_u32()?)
+                .filter(|&doc| doc < self.header.doc_count)
+                .ok_or_else(|| corrupt("document out of range in postings"))?;
+            let count = cursor.varint_u32()?;
+            let positions = Positions {
+                title: cursor.positions()?,
+                description: cursor.positions()?,
+                body: cursor.positions()?,
+            };
+            postings.push(Posting {
+                doc,
+                count,
+                positions,
+            });
+        }
+        Ok(postings)
+    }
+
+    pub fn document(&self, doc: u32) -> Result<StoredDocument, SegmentError> {
+        if doc >= self.header.doc_count {
+            return Err(corrupt("document out of range"));
+        }
+        let offset = self.cursor(self.header.stored + doc as usize * 8).u64()? as usize;
+        if offset >= self.header.removed {
+            return Err(corrupt("stored fields offset out of bounds"));
+        }
+        let mut cursor = self.cursor(offset);
+        let id = cursor.object_id()?;
+        let mut lengths = [0; 3];
+        for length in &mut lengths {
+            *length = cursor.varint_u32()?;
+        }
+        Ok(StoredDocument {
+            id,
+            lengths,
+            url: cursor.string()?,
+            title: cursor.string()?,
+            description: cursor.string()?,
+        })
+    }
+
+    pub fn documents(&self) -> Result<Vec<StoredDocument>, SegmentError> {
+        (0..self.header.doc_count)
+            .map(|doc| self.document(doc))
+            .collect()
+    }
+
+    // Documents this segment hides in older segments
+    pub fn removed(&self) -> Result<Vec<ObjectId>, SegmentError> {
+        let mut cursor = self.cursor(self.header.removed);
+        (0..self.header.removed_count)
+            .map(|_| cursor.object_id())
+            .collect()
+    }
+}
+
+// Every segment in a directory, oldest first. A document lives in the newest
+// segment that has it, unless a segment after that one removed it.
+pub struct Index {
+    files: Vec<SegmentFile>,
+    segments: Vec<Segment>,
+    // Documents of each segment that a newer segment replaced or removed
+    deleted: Vec<HashSet<u32>>,
+}
+
+impl Index {
+    pub fn open(dir: &Path) -> Result<Index, SegmentError> {
+        // A merge may delete a listed file before it is opened, in which
+        // case the directory is listed again
+        let mut attempts = 0;
+        loop {
+            match Index::open_files(segment_files(dir)?) {
+                Err(SegmentError::Io(e)) if e.kind() == io::ErrorKind::NotFound && attempts < 3 => {
+                    attempts += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn open_files(files: Vec<SegmentFile>) -> Result<Index, SegmentError> {
+        let mut segments = Vec::new();
+        for file in &files {
+            segments.push(Segment::open(&file.path)?);
+        }
+        let mut deleted = vec![HashSet::new(); segments.len()];
+        let mut newest: HashMap<ObjectId, (usize, u32)> = HashMap::new();
+        for (index, segment) in segments.iter().enumerate() {
+            // Removals only apply to older segments, so they go first
+            for id in segment.removed()? {
+                if let Some((older, doc)) = newest.remove(&id) {
+                    deleted[older].insert(doc);
+                }
+            }
+            for doc in 0..segment.doc_count() {
+                let id = segment.document(doc)?.id;
+                if let Some((older, doc)) = newest.insert(id, (index, doc)) {
+                    deleted[older].insert(doc);
+                }
+            }
+        }
+        Ok(Index {
+            files,
+            segments,
+            deleted,
+        })
+    }
+
+    pub fn files(&self) -> &[SegmentFile] {
+        &self.files
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn is_deleted(&self, segment: usize, doc: u32) -> bool {
+        self.deleted[segment].contains(&doc)
+    }
+
+    // Documents of `segment` a newer segment replaced or removed
+    pub fn deleted_count(&self, segment: usize) -> usize {
+        self.deleted[segment].len()
+    }
+
+    // Number of live documents across every segment
+    pub fn doc_count(&self) -> usize {
+        self.segments
+            .iter()
+            .zip(&self.deleted)
+            .map(|(segment, deleted)| segment.doc_count() as usize - deleted.len())
+            .sum()
+    }
+
+    // Every live document containing `term`, with its entry in the term's
+    // postings list
+    pub fn postings(&self, term: &str) -> Result<Vec<(StoredDocument, Posting)>, SegmentError> {
+        let mut found = Vec::new();
+        for (index, segment) in self.segments.iter().enumerate() {
+            for posting in segment.postings(term)? {
+                if !self.is_deleted(index, posting.doc) {
+                    found.push((segment.document(posting.doc)?, posting));
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(document: &Document, postings: &[(&str, Positions)]) -> Vec<Words> {
+        postings
+            .iter()
+            .map(|(word, positions)| {
+                let count = (positions.title.len() * 50 + positions.body.len()) as i32;
+                let mut words = Words::new(document.get_id(), word.to_string(), count);
+                words.positions = positions.clone();
+                words
+            })
+            .collect()
+    }
+
+    fn body(positions: &[u32]) -> Positions {
+        Positions {
+            body: positions.to_vec(),
+            ..Positions::default()
+        }
+    }
+
+    fn page(url: &str) -> Document {
+        Document {
+            url: url.to_string(),
+            ..Document::default()
+        }
+    }
+
+    #[test]
+    fn test_segment_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = page("https://example.com/a");
+        let second = page("https://example.com/b");
+        let removed = ObjectId::new();
+        let mut writer = SegmentWriter::new();
+        let crab = Positions {
+            title: vec![0],
+            body: vec![1, 300, 70000],
+            ..Positions::default()
+        };
+        writer.add(
+            &first,
+            words(&first, &[("crab", crab.clone()), ("rust", body(&[0]))]),
+        );
+        writer.add(&second, words(&second, &[("crab", body(&[4]))]));
+        writer.remove(removed);
+        let path = writer.write(dir.path()).unwrap();
+        assert!(path.ends_with("0000000001.seg"));
+
+        let segment = Segment::open(&path).unwrap();
+        assert_eq!(segment.doc_count(), 2);
+        assert_eq!(segment.term_count(), 2);
+        assert_eq!(segment.terms().unwrap(), ["crab", "rust"]);
+        assert_eq!(segment.doc_freq("crab").unwrap(), 2);
+        assert_eq!(segment.doc_freq("missing").unwrap(), 0);
+        assert_eq!(segment.removed().unwrap(), [removed]);
+
+        let postings = segment.postings("crab").unwrap();
+        assert_eq!(postings.len(), 2);
+        let stored = segment.document(postings[0].doc).unwrap();
+        assert_eq!(stored.id, first.get_id());
+        assert_eq!(stored.url, first.url);
+        assert_eq!(stored.title, first.title);
+        assert_eq!(stored.length(Field::Body), 70001);
+        assert_eq!(stored.length(Field::Title), 1);
+        assert_eq!(postings[0].positions, crab);
+        assert_eq!(postings[0].count, 53);
+        assert_eq!(
+            segment
+                .document(postings[1].doc)
+                .unwrap()
+                .length(Field::Body),
+            5
+        );
+        assert_eq!(segment.average_length(Field::Body), 35003.0);
+        assert!(segment.postings("missing").unwrap().is_empty());
+        assert!(segment.document(2).is_err());
+    }
+
+    #[test]
+    fn test_segment_rejects_damage() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = page("https://example.com/");
+        let mut writer = SegmentWriter::new();
+        writer.add(&document, words(&document, &[("crab", body(&[0]))]));
+        let path = writer.write(dir.path()).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let mut flipped = bytes.clone();
+        flipped[HEADER_LEN] ^= 1;
+        fs::write(&path, &flipped).unwrap();
+        assert!(matches!(
+            Segment::open(&path),
+            Err(SegmentError::Corrupt(_))
+        ));
+
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(
+            Segment::open(&path),
+            Err(SegmentError::Corrupt(_))
+        ));
+
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()] = 2;
+        fs::write(&path, &newer).unwrap();
+        assert!(matches!(
+            Segment::open(&path),
+            Err(SegmentError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn test_index_hides_replaced_and_removed_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let kept = page("https://example.com/kept");
+        let mut replaced = page("https://example.com/replaced");
+        let removed = page("https://example.com/removed");
+
+        let mut writer = SegmentWriter::new();
+        for document in [&kept, &replaced, &removed] {
+            writer.add(document, words(document, &[("crab", body(&[0]))]));
+        }
+        writer.write(dir.path()).unwrap();
+
+        replaced.title = "Updated".to_string();
+        let mut writer = SegmentWriter::new();
+        writer.add(&replaced, words(&replaced, &[("crab", body(&[0]))]));
+        writer.remove(removed.get_id());
+        writer.write(dir.path()).unwrap();
+
+        let index = Index::open(dir.path()).unwrap();
+        assert_eq!(index.segments().len(), 2);
+        assert_eq!(index.doc_count(), 2);
+        assert_eq!(index.deleted_count(0), 2);
+        let live = index.postings("crab").unwrap();
+        assert_eq!(live.len(), 2);
+        assert!(live.iter().any(|(d, _)| d.id == kept.get_id()));
+        assert!(live.iter().any(|(d, _)| d.title == "Updated"));
+        assert!(index.postings("missing").unwrap().is_empty());
+    }
+}
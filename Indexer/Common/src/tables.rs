@@ -0,0 +1,34 @@
+// Tables of the embedded redb backend. Values are BSON so records look the
+// same as in MongoDB.
+use redb::TableDefinition;
+
+use crate::models::TfIdfScore;
+
+// document id -> Document
+pub const DOCUMENTS: TableDefinition<&str, &[u8]> = TableDefinition::new("documents");
+// url, redirect target or canonical url -> document id
+pub const URLS: TableDefinition<&str, &str> = TableDefinition::new("urls");
+// "<document id>/<word id>" -> Words, from before schema version 3. Only
+// kept so the migration can drop it.
+pub const WORDS: TableDefinition<&str, &[u8]> = TableDefinition::new("words");
+// url -> FrontierEntry
+pub const FRONTIER: TableDefinition<&str, &[u8]> = TableDefinition::new("frontier");
+// url -> DeadLetter
+pub const DEAD_LETTERS: TableDefinition<&str, &[u8]> = TableDefinition::new("dead_letters");
+// "<word>\0<score id>" -> TfIdfScore, so the scores of a word are one range.
+// Files written when scores were keyed by id alone need the TF-IDF job run
+// again.
+pub const TF_IDF_SCORES: TableDefinition<&str, &[u8]> = TableDefinition::new("tf_idf_scores");
+// Metadata::ID -> Metadata
+pub const METADATA: TableDefinition<&str, &[u8]> = TableDefinition::new("metadata");
+
+// Where a score is stored in TF_IDF_SCORES
+pub fn score_key(score: &TfIdfScore) -> String {
+    format!("{}{}", score_prefix(&score.word), score._id.to_hex())
+}
+
+// What the key of every score of `word` starts with. The separator sorts
+// before any character of a word, so `crab` does not run into `crabs`.
+pub fn score_prefix(word: &str) -> String {
+    format!("{}\0", word)
+}
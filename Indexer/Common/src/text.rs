@@ -0,0 +1,50 @@
+// Text cleaning shared by the indexer and the query engine. Queries have to
+// go through exactly the same steps as pages for their terms to match.
+use human_regex::{exactly, one_or_more, or, punctuation, whitespace, word_boundary};
+use stop_words::{get as sget, LANGUAGE};
+
+pub fn clean_corpus(document: String) -> String {
+    let words = sget(LANGUAGE::English);
+    //println!("{:?}", words);
+    // Remove punctuation and lowercase the text to make parsing easier
+    let lowercase_doc = document.to_ascii_lowercase();
+    let regex_for_punctuation = one_or_more(punctuation());
+
+    //println!("{}", regex_for_punctuation.to_regex());
+
+    let text_without_punctuation = regex_for_punctuation
+        .to_regex()
+        .replace_all(&lowercase_doc, " ");
+    // Make a regex to match stopwords with trailing spaces and punctuation
+    let regex_for_stop_words =
+        word_boundary() + exactly(1, or(&words)) + word_boundary() + one_or_more(whitespace());
+    //println!("{}", regex_for_stop_words.to_regex());
+    // Remove stop words
+    let clean_text = regex_for_stop_words
+        .to_regex()
+        .replace_all(&text_without_punctuation, "");
+    clean_text.to_string()
+}
+
+// The terms `text` is indexed or searched under, in order
+pub fn tokenize(text: String) -> Vec<String> {
+    clean_corpus(text)
+        .split(" ")
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("The Crab, and the Lobster!".to_string()),
+            ["crab", "lobster"]
+        );
+        assert_eq!(tokenize("the and of crabs".to_string()), ["crabs"]);
+    }
+}
@@ -1,154 +1,382 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use common::{
+    db::{connect, Collections, METADATA},
+    models::Metadata,
+    schema::{self, IndexSpec},
+};
+use futures::TryStreamExt;
 use mongodb::{
-    bson::doc,
+    bson::{self, doc, oid::ObjectId, to_bson, DateTime},
     error::Error,
-    results::{InsertManyResult, InsertOneResult},
-    Client, Collection,
+    Collection,
+};
+
+use crate::{
+    errors::StateEvents,
+    models::{DeadLetter, Document, FrontierEntry, FrontierState},
+    storage::{scheme_variants, Storage, StorageError},
 };
 
-use crate::models::{Document, Words};
+const FRONTIER: &str = "frontier";
+const DEAD_LETTERS: &str = "dead_letters";
+
+// Every index the indexer relies on, its own collections included
+pub fn indexes() -> Vec<IndexSpec> {
+    let mut indexes = schema::shared_indexes();
+    indexes.extend([
+        // Frontier entries and dead letters are upserted by URL
+        IndexSpec::unique(FRONTIER, doc! { "url": 1 }),
+        IndexSpec::new(FRONTIER, doc! { "state": 1 }),
+        IndexSpec::unique(DEAD_LETTERS, doc! { "url": 1 }),
+        // The scheduler looks for the earliest due revisits
+        IndexSpec::new(common::db::DOCUMENTS, doc! { "next_fetch_at": 1 }),
+        // Startup looks for pages whose words never reached a segment
+        IndexSpec::new(common::db::DOCUMENTS, doc! { "unindexed": 1 }),
+    ]);
+    indexes
+}
+
 pub struct Database {
-    words: Collection<Words>,
-    documents: Collection<Document>,
+    collections: Collections,
+    frontier: Collection<FrontierEntry>,
+    dead_letters: Collection<DeadLetter>,
+    metadata: Collection<Metadata>,
 }
 
 impl Database {
     pub async fn new() -> Database {
-        let url = match dotenv::var("MONGODB_URI") {
-            Ok(url) => url,
-            Err(_) => "mongodb://localhost:27017".to_string(),
-        };
+        let db = connect().await;
+        schema::setup(&db, &indexes()).await;
+        let frontier: Collection<FrontierEntry> = db.collection(FRONTIER);
+        let dead_letters: Collection<DeadLetter> = db.collection(DEAD_LETTERS);
 
-        let client = match Client::with_uri_str(url).await {
-            Ok(client) => {
-                println!("DB connected succesfully");
-                client
-            }
-            Err(e) => {
-                panic!("{}", e);
-            }
+        Database {
+            collections: Collections::new(&db),
+            frontier,
+            dead_letters,
+            metadata: db.collection(METADATA),
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for Database {
+    async fn try_commit(&self, document: Document) -> Result<(), StorageError> {
+        let document = Document {
+            unindexed: true,
+            ..document
         };
-        let db = client.database("SearchEngine");
+        self.collections.documents.insert_one(document).await?;
+        Ok(())
+    }
+    async fn replace_commit(&self, document: Document) -> Result<(), StorageError> {
+        let document = Document {
+            unindexed: true,
+            ..document
+        };
+        self.collections
+            .documents
+            .replace_one(doc! { "_id": document.get_id() }, &document)
+            .await?;
+        Ok(())
+    }
 
-        let words: Collection<Words> = db.collection("words");
-        let documents: Collection<Document> = db.collection("documents");
+    async fn mark_indexed(&self, ids: &[ObjectId]) -> Result<(), StorageError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.collections
+            .documents
+            .update_many(
+                doc! { "_id": { "$in": ids } },
+                doc! { "$set": { "unindexed": false } },
+            )
+            .await?;
+        Ok(())
+    }
 
-        Database { words, documents }
+    async fn unindexed_documents(&self, limit: i64) -> Result<Vec<Document>, StorageError> {
+        let documents = self
+            .collections
+            .documents
+            .find(doc! { "unindexed": true })
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(documents)
     }
-    pub async fn insert_words(&self, words: Vec<Words>) -> Result<InsertManyResult, Error> {
-        let result = match self.words.insert_many(words).await {
-            Ok(res) => {
-                println!("Insertion Successful");
-                res
-            }
-            Err(e) => {
-                panic!("word Insertion failed:{}", e);
-            }
-        };
 
-        Ok(result)
+    async fn drop_words(&self) -> Result<(), StorageError> {
+        Ok(self.collections.words.drop().await?)
     }
-    pub async fn insert_document(&self, document: Document) -> Result<InsertOneResult, Error> {
-        let result = match self.documents.insert_one(document).await {
-            Ok(res) => {
-                println!("Insertion Successful");
-                res
-            }
-            Err(e) => {
-                panic!("Document Insertion failed:{}", e);
+
+    async fn find_document(&self, url: &str) -> Result<Option<Document>, StorageError> {
+        Ok(self.collections.documents.find_one(url_filter(url)).await?)
+    }
+
+    async fn document_urls(&self) -> Result<Vec<String>, StorageError> {
+        let mut cursor = self
+            .collections
+            .documents
+            .clone_with_type::<bson::Document>()
+            .find(doc! {})
+            .projection(doc! { "url": 1 })
+            .await?;
+        let mut urls = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            if let Ok(url) = document.get_str("url") {
+                urls.push(url.to_string());
             }
-        };
-        Ok(result)
+        }
+        Ok(urls)
     }
-    pub async fn delete_documents(&self, url: &str) -> Result<(), Error> {
-        let result = self
+
+    async fn touch_document(&self, document: &Document) -> Result<(), StorageError> {
+        let set = doc! {
+            "etag": &document.etag,
+            "last_modified": &document.last_modified,
+            "fetched_at": document.fetched_at,
+            "fetch_count": document.fetch_count,
+            "change_count": document.change_count,
+            "revisit_secs": document.revisit_secs,
+            "next_fetch_at": document.next_fetch_at,
+        };
+        self.collections
             .documents
-            .delete_one(doc! { "url": url })
-            .await;
+            .update_one(doc! { "_id": document.get_id() }, doc! { "$set": set })
+            .await?;
+        Ok(())
+    }
 
-        match result {
-            Ok(res) => {
-                println!("Document deleted successfully");
-                Ok(())
-            }
-            Err(e) => {
-                println!("Error deleting document: {}", e);
-                Err(e)
+    async fn due_urls(&self, limit: i64) -> Result<Vec<String>, StorageError> {
+        let filter = doc! {
+            "$or": [
+                { "next_fetch_at": { "$lte": DateTime::now() } },
+                { "next_fetch_at": null },
+            ]
+        };
+        let mut cursor = self
+            .collections
+            .documents
+            .clone_with_type::<bson::Document>()
+            .find(filter)
+            .sort(doc! { "next_fetch_at": 1 })
+            .limit(limit)
+            .projection(doc! { "url": 1 })
+            .await?;
+        let mut urls = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            if let Ok(url) = document.get_str("url") {
+                urls.push(url.to_string());
             }
         }
+        Ok(urls)
     }
-    pub async fn try_commit(&self, documents: Document, words: Vec<Words>) -> bool {
-        let mut document_session = self.documents.client().start_session().await.unwrap();
-        let mut word_session = self.words.client().start_session().await.unwrap();
-        let url = documents.url.clone();
-        document_session.start_transaction().await;
-        word_session.start_transaction().await;
 
-        match self
+    async fn postpone_revisits(
+        &self,
+        urls: &[String],
+        delay: Duration,
+    ) -> Result<(), StorageError> {
+        let until =
+            DateTime::from_millis(DateTime::now().timestamp_millis() + delay.as_millis() as i64);
+        self.collections
             .documents
-            .insert_one(documents)
-            .session(&mut document_session)
-            .await
-        {
-            Ok(_) => {},
-            Err(e) => {
-                document_session.abort_transaction().await;
-                return false;
-            }
+            .update_many(
+                doc! { "url": { "$in": urls } },
+                doc! { "$set": { "next_fetch_at": until } },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_document(&self, url: &str) -> Result<u64, StorageError> {
+        let filter = url_filter(url);
+        let ids: Vec<_> = self
+            .collections
+            .documents
+            .find(filter.clone())
+            .await?
+            .try_collect::<Vec<Document>>()
+            .await?
+            .iter()
+            .map(|document| document.get_id())
+            .collect();
+        if ids.is_empty() {
+            return Ok(0);
         }
-        match self
-            .words
-            .insert_many(words)
-            .session(&mut word_session)
+        let result = self
+            .collections
+            .documents
+            .delete_many(doc! { "_id": { "$in": &ids } })
+            .await?;
+        Ok(result.deleted_count)
+    }
+    async fn url_exists(&self, url: &str) -> Result<bool, StorageError> {
+        let count = match self
+            .collections
+            .documents
+            .count_documents(url_filter(url))
             .await
         {
-            Ok(_) => {},
+            Ok(count) => count,
             Err(e) => {
-                word_session.abort_transaction().await;
-                return false;
+                println!("Error checking URL existence: {}", e);
+                return Err(e.into());
             }
+        };
+        Ok(count > 0)
+    }
+
+    async fn frontier_enqueue(
+        &self,
+        url: &str,
+        depth: u32,
+        lastmod: Option<String>,
+    ) -> Result<bool, StorageError> {
+        let entry = FrontierEntry::new(url.to_string(), depth, lastmod);
+        let entry = to_bson(&entry).map_err(Error::custom)?;
+        let result = self
+            .frontier
+            .update_one(doc! { "url": url }, doc! { "$setOnInsert": entry })
+            .upsert(true)
+            .await?;
+        Ok(result.upserted_id.is_some())
+    }
+
+    async fn frontier_mark(
+        &self,
+        url: &str,
+        state: FrontierState,
+        reason: Option<StateEvents>,
+        error: Option<String>,
+    ) -> Result<(), StorageError> {
+        let state = to_bson(&state).map_err(Error::custom)?;
+        let reason = to_bson(&reason).map_err(Error::custom)?;
+        self.frontier
+            .update_one(
+                doc! { "url": url },
+                doc! {
+                    "$set": {
+                        "state": state,
+                        "reason": reason,
+                        "error": error,
+                        "updated_at": DateTime::now(),
+                    }
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn frontier_resume(&self) -> Result<Vec<FrontierEntry>, StorageError> {
+        let queued = to_bson(&FrontierState::Queued).map_err(Error::custom)?;
+        let in_flight = to_bson(&FrontierState::InFlight).map_err(Error::custom)?;
+        let reset = self
+            .frontier
+            .update_many(
+                doc! { "state": in_flight },
+                doc! { "$set": { "state": queued, "updated_at": DateTime::now() } },
+            )
+            .await?;
+        if reset.modified_count > 0 {
+            println!(
+                "Requeued {} in-flight URLs from the last run",
+                reset.modified_count
+            );
         }
 
-        match (
-            document_session.commit_transaction().await,
-            word_session.commit_transaction().await,
-        ) {
-            (Ok(_), Ok(_)) => {
-                //println!("Both transactions committed successfully");
-                true
-            }
-            (Err(e), _) => {
-                println!("Document transaction failed: {} for: {}", e, url);
+        let cursor = self
+            .frontier
+            .find(doc! {})
+            .sort(doc! { "depth": 1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
 
-                false
-            }
-            (_, Err(e)) => {
-                println!("Word transaction failed: {} for: {}", e, url);
+    async fn dead_letter_add(&self, letter: DeadLetter) -> Result<(), StorageError> {
+        let url = letter.url.clone();
+        self.dead_letters
+            .replace_one(doc! { "url": &url }, letter)
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
 
-                false
-            }
+    async fn dead_letters_requeue(&self) -> Result<usize, StorageError> {
+        let letters: Vec<DeadLetter> = self.dead_letters.find(doc! {}).await?.try_collect().await?;
+        let queued = to_bson(&FrontierState::Queued).map_err(Error::custom)?;
+        for letter in &letters {
+            self.frontier
+                .update_one(
+                    doc! { "url": &letter.url },
+                    doc! {
+                        "$set": {
+                            "state": &queued,
+                            "reason": null,
+                            "error": null,
+                            "updated_at": DateTime::now(),
+                        },
+                        "$setOnInsert": { "depth": letter.depth },
+                    },
+                )
+                .upsert(true)
+                .await?;
+            self.dead_letters
+                .delete_one(doc! { "_id": letter._id })
+                .await?;
         }
+        Ok(letters.len())
     }
-    pub async fn insert_word(&self, word: Words) -> Result<InsertOneResult, Error> {
-        let result = match self.words.insert_one(word).await {
-            Ok(res) => {
-                println!("Insertion Successful");
-                res
-            }
-            Err(e) => {
-                panic!("word Insertion failed:{}", e);
-            }
-        };
-        Ok(result)
+
+    async fn schema_version(&self) -> Result<Option<u32>, StorageError> {
+        let metadata = self.metadata.find_one(doc! { "_id": Metadata::ID }).await?;
+        Ok(metadata.map(|metadata| metadata.schema_version))
     }
-    pub async fn url_exists(&self, url: &str) -> Result<bool, Error> {
-        let count = match self.documents.count_documents(doc! { "url": url }).await {
-            Ok(count) => count,
-            Err(e) => {
-                println!("Error checking URL existence: {}", e);
-                return Err(e);
-            }
+
+    async fn set_schema_version(&self, version: u32) -> Result<(), StorageError> {
+        self.metadata
+            .replace_one(doc! { "_id": Metadata::ID }, Metadata::new(version))
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    async fn outdated_documents(
+        &self,
+        version: u32,
+        limit: i64,
+    ) -> Result<Vec<Document>, StorageError> {
+        // Records from before versioning have no schema_version at all
+        let filter = doc! {
+            "$or": [
+                { "schema_version": { "$lt": version } },
+                { "schema_version": { "$exists": false } },
+            ]
         };
-        Ok(count > 0)
+        let documents = self
+            .collections
+            .documents
+            .find(filter)
+            .limit(limit)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(documents)
+    }
+}
+
+fn url_filter(url: &str) -> bson::Document {
+    let variants = scheme_variants(url);
+    doc! {
+        "$or": [
+            { "url": { "$in": &variants } },
+            { "final_url": { "$in": &variants } },
+            { "canonical_url": { "$in": &variants } },
+        ]
     }
 }
 
@@ -158,15 +386,17 @@ mod tests {
     #[tokio::test]
     async fn test_check_url() {
         let db = Database::new().await;
-        db.insert_document(Document {
-            url: "https://docs.rs/url/latest/url/".to_string(),
-            ..Document::default()
-        })
-        .await.unwrap();
+        db.collections
+            .insert_document(Document {
+                url: "https://docs.rs/url/latest/url/".to_string(),
+                ..Document::default()
+            })
+            .await
+            .unwrap();
         let url = "https://docs.rs/url/latest/url/";
         let exists = db.url_exists(url).await.unwrap();
         assert!(exists);
-        db.delete_documents(url).await.unwrap();
+        db.collections.delete_documents(url).await.unwrap();
     }
     #[tokio::test]
     async fn test_check_url_dne() {
@@ -175,68 +405,4 @@ mod tests {
         let exists = db.url_exists(url).await.unwrap();
         assert!(!exists);
     }
-
-    #[tokio::test]
-    async fn test_transaction_abort() {
-        let db = Database::new().await;
-
-        // Create example document and words
-        let test_document = Document {
-            url: "https://test-abort-transaction.com".to_string(),
-            ..Document::default()
-        };
-
-        let test_words = vec![
-            Words {
-                word: "test".to_string(),
-                document: test_document.get_id(),
-                ..Words::default()
-            },
-            Words {
-                word: "abort".to_string(),
-                document: test_document.get_id(),
-                ..Words::default()
-            },
-        ];
-
-        // Verify the URL doesn't exist before the test
-        let exists_before = db.url_exists(&test_document.url).await.unwrap();
-        assert!(
-            !exists_before,
-            "Test URL should not exist before transaction"
-        );
-
-        // Start transaction and insert data
-        let mut document_session = db.documents.client().start_session().await.unwrap();
-        let mut word_session = db.words.client().start_session().await.unwrap();
-
-        document_session.start_transaction().await.unwrap();
-        word_session.start_transaction().await.unwrap();
-
-        // Insert document and words within transaction
-        let _doc_result = db
-            .documents
-            .insert_one(&test_document)
-            .session(&mut document_session)
-            .await;
-
-        let _word_result = db
-            .words
-            .insert_many(&test_words)
-            .session(&mut word_session)
-            .await;
-
-        // Abort both transactions
-        document_session.abort_transaction().await.unwrap();
-        word_session.abort_transaction().await.unwrap();
-
-        // Verify the data was not committed (URL should still not exist)
-        let exists_after = db.url_exists(&test_document.url).await.unwrap();
-        assert!(
-            !exists_after,
-            "Test URL should not exist after transaction abort"
-        );
-
-        println!("Transaction abort test completed successfully");
-    }
 }
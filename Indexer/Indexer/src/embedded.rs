@@ -0,0 +1,606 @@
+use std::{collections::BTreeSet, path::Path, time::Duration};
+
+use async_trait::async_trait;
+use common::{
+    models::Metadata,
+    tables::{DEAD_LETTERS, DOCUMENTS, FRONTIER, METADATA, URLS, WORDS},
+};
+use mongodb::bson::{self, oid::ObjectId, DateTime};
+use redb::{ReadableTable, WriteTransaction};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    errors::StateEvents,
+    models::{DeadLetter, Document, FrontierEntry, FrontierState},
+    storage::{scheme_variants, Storage, StorageError},
+};
+
+// Storage in a single redb file, for running without a MongoDB server.
+// redb calls are blocking but short, so they run on the calling task.
+pub struct EmbeddedStorage {
+    db: redb::Database,
+}
+
+impl EmbeddedStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<EmbeddedStorage, StorageError> {
+        let db = redb::Database::create(path)?;
+        // Create every table up front so readers never find one missing
+        let txn = db.begin_write()?;
+        txn.open_table(DOCUMENTS)?;
+        txn.open_table(URLS)?;
+        txn.open_table(FRONTIER)?;
+        txn.open_table(DEAD_LETTERS)?;
+        txn.open_table(METADATA)?;
+        txn.commit()?;
+        Ok(EmbeddedStorage { db })
+    }
+
+    fn document_ids(&self, url: &str) -> Result<BTreeSet<String>, StorageError> {
+        let txn = self.db.begin_read()?;
+        let urls = txn.open_table(URLS)?;
+        let mut ids = BTreeSet::new();
+        for variant in scheme_variants(url) {
+            if let Some(id) = urls.get(variant.as_str())? {
+                ids.insert(id.value().to_string());
+            }
+        }
+        Ok(ids)
+    }
+
+    fn load_document(&self, id: &str) -> Result<Option<Document>, StorageError> {
+        let txn = self.db.begin_read()?;
+        let documents = txn.open_table(DOCUMENTS)?;
+        let document = documents.get(id)?;
+        document.map(|bytes| decode(bytes.value())).transpose()
+    }
+
+    fn all_documents(&self) -> Result<Vec<Document>, StorageError> {
+        let txn = self.db.begin_read()?;
+        let documents = txn.open_table(DOCUMENTS)?;
+        let mut all = Vec::new();
+        for entry in documents.iter()? {
+            let (_, bytes) = entry?;
+            all.push(decode(bytes.value())?);
+        }
+        Ok(all)
+    }
+
+    // Writes a document with its url index entries, replacing whatever was
+    // stored under its id before. It stays unindexed until `mark_indexed`.
+    fn write_page(txn: &WriteTransaction, document: &Document) -> Result<(), StorageError> {
+        let id = document.get_id().to_hex();
+        Self::delete_page(txn, &id)?;
+
+        let stored = Document {
+            unindexed: true,
+            ..document.clone()
+        };
+        txn.open_table(DOCUMENTS)?
+            .insert(id.as_str(), encode(&stored)?.as_slice())?;
+        let mut urls = txn.open_table(URLS)?;
+        for alias in aliases(document) {
+            urls.insert(alias, id.as_str())?;
+        }
+        Ok(())
+    }
+
+    // Removes a document and the url index entries pointing at it. Returns
+    // whether there was anything to remove.
+    fn delete_page(txn: &WriteTransaction, id: &str) -> Result<bool, StorageError> {
+        let previous: Option<Document> = {
+            let mut documents = txn.open_table(DOCUMENTS)?;
+            let removed = documents.remove(id)?;
+            removed.map(|bytes| decode(bytes.value())).transpose()?
+        };
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Ok(false),
+        };
+
+        let mut urls = txn.open_table(URLS)?;
+        for alias in aliases(&previous) {
+            // Only drop index entries that still point at this document
+            let points_here = urls.get(alias)?.is_some_and(|owner| owner.value() == id);
+            if points_here {
+                urls.remove(alias)?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn update_document(
+        &self,
+        id: &str,
+        update: impl FnOnce(&mut Document),
+    ) -> Result<(), StorageError> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut documents = txn.open_table(DOCUMENTS)?;
+            let stored: Option<Document> = {
+                let bytes = documents.get(id)?;
+                bytes.map(|bytes| decode(bytes.value())).transpose()?
+            };
+            if let Some(mut document) = stored {
+                update(&mut document);
+                documents.insert(id, encode(&document)?.as_slice())?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for EmbeddedStorage {
+    async fn url_exists(&self, url: &str) -> Result<bool, StorageError> {
+        Ok(!self.document_ids(url)?.is_empty())
+    }
+
+    async fn find_document(&self, url: &str) -> Result<Option<Document>, StorageError> {
+        for id in self.document_ids(url)? {
+            if let Some(document) = self.load_document(&id)? {
+                return Ok(Some(document));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn document_urls(&self) -> Result<Vec<String>, StorageError> {
+        let documents = self.all_documents()?;
+        Ok(documents.into_iter().map(|document| document.url).collect())
+    }
+
+    async fn due_urls(&self, limit: i64) -> Result<Vec<String>, StorageError> {
+        let now = DateTime::now();
+        let mut due: Vec<Document> = self
+            .all_documents()?
+            .into_iter()
+            .filter(|document| document.next_fetch_at.is_none_or(|at| at <= now))
+            .collect();
+        // Never scheduled sorts first, as null does in MongoDB
+        due.sort_by_key(|document| document.next_fetch_at);
+        Ok(due
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .map(|document| document.url)
+            .collect())
+    }
+
+    async fn postpone_revisits(
+        &self,
+        urls: &[String],
+        delay: Duration,
+    ) -> Result<(), StorageError> {
+        let until =
+            DateTime::from_millis(DateTime::now().timestamp_millis() + delay.as_millis() as i64);
+        for url in urls {
+            for id in self.document_ids(url)? {
+                self.update_document(&id, |document| {
+                    if document.url == *url {
+                        document.next_fetch_at = Some(until);
+                    }
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn touch_document(&self, document: &Document) -> Result<(), StorageError> {
+        self.update_document(&document.get_id().to_hex(), |stored| {
+            stored.etag = document.etag.clone();
+            stored.last_modified = document.last_modified.clone();
+            stored.fetched_at = document.fetched_at;
+            stored.fetch_count = document.fetch_count;
+            stored.change_count = document.change_count;
+            stored.revisit_secs = document.revisit_secs;
+            stored.next_fetch_at = document.next_fetch_at;
+        })
+    }
+
+    async fn try_commit(&self, document: Document) -> Result<(), StorageError> {
+        let txn = self.db.begin_write()?;
+        {
+            let documents = txn.open_table(DOCUMENTS)?;
+            if documents
+                .get(document.get_id().to_hex().as_str())?
+                .is_some()
+            {
+                return Err(StorageError::Embedded(format!(
+                    "document {} already exists",
+                    document.get_id()
+                )));
+            }
+        }
+        Self::write_page(&txn, &document)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    async fn replace_commit(&self, document: Document) -> Result<(), StorageError> {
+        let txn = self.db.begin_write()?;
+        Self::write_page(&txn, &document)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    async fn mark_indexed(&self, ids: &[ObjectId]) -> Result<(), StorageError> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut documents = txn.open_table(DOCUMENTS)?;
+            for id in ids {
+                let id = id.to_hex();
+                let stored: Option<Document> = {
+                    let bytes = documents.get(id.as_str())?;
+                    bytes.map(|bytes| decode(bytes.value())).transpose()?
+                };
+                if let Some(mut document) = stored {
+                    document.unindexed = false;
+                    documents.insert(id.as_str(), encode(&document)?.as_slice())?;
+                }
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    async fn unindexed_documents(&self, limit: i64) -> Result<Vec<Document>, StorageError> {
+        Ok(self
+            .all_documents()?
+            .into_iter()
+            .filter(|document| document.unindexed)
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+
+    async fn remove_document(&self, url: &str) -> Result<u64, StorageError> {
+        let ids = self.document_ids(url)?;
+        let txn = self.db.begin_write()?;
+        let mut removed = 0;
+        for id in ids {
+            if Self::delete_page(&txn, &id)? {
+                removed += 1;
+            }
+        }
+        txn.commit()?;
+        Ok(removed)
+    }
+
+    async fn frontier_enqueue(
+        &self,
+        url: &str,
+        depth: u32,
+        lastmod: Option<String>,
+    ) -> Result<bool, StorageError> {
+        let txn = self.db.begin_write()?;
+        let inserted = {
+            let mut frontier = txn.open_table(FRONTIER)?;
+            if frontier.get(url)?.is_some() {
+                false
+            } else {
+                let entry = FrontierEntry::new(url.to_string(), depth, lastmod);
+                frontier.insert(url, encode(&entry)?.as_slice())?;
+                true
+            }
+        };
+        txn.commit()?;
+        Ok(inserted)
+    }
+
+    async fn frontier_mark(
+        &self,
+        url: &str,
+        state: FrontierState,
+        reason: Option<StateEvents>,
+        error: Option<String>,
+    ) -> Result<(), StorageError> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut frontier = txn.open_table(FRONTIER)?;
+            let stored: Option<FrontierEntry> = {
+                let bytes = frontier.get(url)?;
+                bytes.map(|bytes| decode(bytes.value())).transpose()?
+            };
+            if let Some(mut entry) = stored {
+                entry.state = state;
+                entry.reason = reason;
+                entry.error = error;
+                entry.updated_at = DateTime::now();
+                frontier.insert(url, encode(&entry)?.as_slice())?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    async fn frontier_resume(&self) -> Result<Vec<FrontierEntry>, StorageError> {
+        let txn = self.db.begin_write()?;
+        let mut entries = Vec::new();
+        let mut reset = 0;
+        {
+            let mut frontier = txn.open_table(FRONTIER)?;
+            for entry in frontier.iter()? {
+                let (_, bytes) = entry?;
+                entries.push(decode::<FrontierEntry>(bytes.value())?);
+            }
+            for entry in entries.iter_mut() {
+                if entry.state == FrontierState::InFlight {
+                    entry.state = FrontierState::Queued;
+                    entry.updated_at = DateTime::now();
+                    frontier.insert(entry.url.as_str(), encode(&*entry)?.as_slice())?;
+                    reset += 1;
+                }
+            }
+        }
+        txn.commit()?;
+        if reset > 0 {
+            println!("Requeued {} in-flight URLs from the last run", reset);
+        }
+        entries.sort_by_key(|entry| entry.depth);
+        Ok(entries)
+    }
+
+    async fn dead_letter_add(&self, letter: DeadLetter) -> Result<(), StorageError> {
+        let txn = self.db.begin_write()?;
+        txn.open_table(DEAD_LETTERS)?
+            .insert(letter.url.as_str(), encode(&letter)?.as_slice())?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    async fn dead_letters_requeue(&self) -> Result<usize, StorageError> {
+        let txn = self.db.begin_write()?;
+        let mut letters = Vec::new();
+        {
+            let mut dead_letters = txn.open_table(DEAD_LETTERS)?;
+            for entry in dead_letters.iter()? {
+                let (_, bytes) = entry?;
+                letters.push(decode::<DeadLetter>(bytes.value())?);
+            }
+            let mut frontier = txn.open_table(FRONTIER)?;
+            for letter in &letters {
+                let stored: Option<FrontierEntry> = {
+                    let bytes = frontier.get(letter.url.as_str())?;
+                    bytes.map(|bytes| decode(bytes.value())).transpose()?
+                };
+                let mut entry = stored
+                    .unwrap_or_else(|| FrontierEntry::new(letter.url.clone(), letter.depth, None));
+                entry.state = FrontierState::Queued;
+                entry.reason = None;
+                entry.error = None;
+                entry.updated_at = DateTime::now();
+                frontier.insert(letter.url.as_str(), encode(&entry)?.as_slice())?;
+                dead_letters.remove(letter.url.as_str())?;
+            }
+        }
+        txn.commit()?;
+        Ok(letters.len())
+    }
+
+    async fn schema_version(&self) -> Result<Option<u32>, StorageError> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(METADATA)?;
+        let metadata = table.get(Metadata::ID)?;
+        let metadata: Option<Metadata> = metadata.map(|bytes| decode(bytes.value())).transpose()?;
+        Ok(metadata.map(|metadata| metadata.schema_version))
+    }
+
+    async fn set_schema_version(&self, version: u32) -> Result<(), StorageError> {
+        let txn = self.db.begin_write()?;
+        txn.open_table(METADATA)?
+            .insert(Metadata::ID, encode(&Metadata::new(version))?.as_slice())?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    async fn drop_words(&self) -> Result<(), StorageError> {
+        let txn = self.db.begin_write()?;
+        txn.delete_table(WORDS)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    async fn outdated_documents(
+        &self,
+        version: u32,
+        limit: i64,
+    ) -> Result<Vec<Document>, StorageError> {
+        Ok(self
+            .all_documents()?
+            .into_iter()
+            .filter(|document| document.schema_version < version)
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+}
+
+// Every URL a document can be found under
+fn aliases(document: &Document) -> BTreeSet<&str> {
+    [
+        document.url.as_str(),
+        document.final_url.as_str(),
+        document.canonical_url.as_str(),
+    ]
+    .into_iter()
+    .filter(|alias| !alias.is_empty())
+    .collect()
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, StorageError> {
+    Ok(bson::to_vec(value)?)
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, StorageError> {
+    Ok(bson::from_slice(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_temp() -> (tempfile::TempDir, EmbeddedStorage) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = EmbeddedStorage::open(dir.path().join("test.redb")).unwrap();
+        (dir, storage)
+    }
+
+    fn page(url: &str) -> Document {
+        Document {
+            url: url.to_string(),
+            canonical_url: String::new(),
+            ..Document::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commit_replace_and_remove() {
+        let (_dir, storage) = open_temp();
+        let document = page("https://example.com/a");
+        let id = document.get_id();
+        storage.try_commit(document.clone()).await.unwrap();
+
+        assert!(storage.url_exists("http://example.com/a").await.unwrap());
+        assert!(!storage.url_exists("https://example.com/b").await.unwrap());
+        assert!(storage.try_commit(document.clone()).await.is_err());
+
+        // A replacement moves the url index
+        let mut updated = document.clone();
+        updated.final_url = "https://example.com/moved".to_string();
+        updated.title = "Updated".to_string();
+        storage.replace_commit(updated).await.unwrap();
+        let found = storage
+            .find_document("https://example.com/moved")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.get_id(), id);
+        assert_eq!(found.title, "Updated");
+
+        assert_eq!(
+            storage
+                .remove_document("https://example.com/a")
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(!storage
+            .url_exists("https://example.com/moved")
+            .await
+            .unwrap());
+        assert!(storage.document_urls().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_frontier_and_dead_letters() {
+        let (_dir, storage) = open_temp();
+        assert!(storage
+            .frontier_enqueue("https://x.com/1", 1, None)
+            .await
+            .unwrap());
+        assert!(!storage
+            .frontier_enqueue("https://x.com/1", 0, None)
+            .await
+            .unwrap());
+        storage
+            .frontier_enqueue("https://x.com/0", 0, None)
+            .await
+            .unwrap();
+        storage
+            .frontier_mark("https://x.com/1", FrontierState::InFlight, None, None)
+            .await
+            .unwrap();
+
+        let entries = storage.frontier_resume().await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].url, "https://x.com/0");
+        assert!(entries
+            .iter()
+            .all(|entry| entry.state == FrontierState::Queued));
+
+        storage
+            .frontier_mark(
+                "https://x.com/1",
+                FrontierState::Failed,
+                None,
+                Some("timed out".to_string()),
+            )
+            .await
+            .unwrap();
+        let letter = DeadLetter::new(
+            "https://x.com/1".to_string(),
+            1,
+            "timeout".to_string(),
+            "timed out".to_string(),
+            3,
+        );
+        storage.dead_letter_add(letter).await.unwrap();
+        assert_eq!(storage.dead_letters_requeue().await.unwrap(), 1);
+        assert_eq!(storage.dead_letters_requeue().await.unwrap(), 0);
+
+        let entries = storage.frontier_resume().await.unwrap();
+        let requeued = entries.iter().find(|e| e.url == "https://x.com/1").unwrap();
+        assert_eq!(requeued.state, FrontierState::Queued);
+        assert_eq!(requeued.error, None);
+    }
+
+    #[tokio::test]
+    async fn test_due_urls() {
+        let (_dir, storage) = open_temp();
+        let mut later = page("https://x.com/later");
+        later.next_fetch_at = Some(DateTime::from_millis(
+            DateTime::now().timestamp_millis() + 60_000,
+        ));
+        let never = page("https://x.com/never");
+        storage.try_commit(later).await.unwrap();
+        storage.try_commit(never).await.unwrap();
+
+        assert_eq!(storage.due_urls(10).await.unwrap(), ["https://x.com/never"]);
+        storage
+            .postpone_revisits(
+                &["https://x.com/never".to_string()],
+                Duration::from_secs(60),
+            )
+            .await
+            .unwrap();
+        assert!(storage.due_urls(10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drop_words() {
+        let (_dir, storage) = open_temp();
+        {
+            let txn = storage.db.begin_write().unwrap();
+            txn.open_table(WORDS)
+                .unwrap()
+                .insert("document/word", [0u8].as_slice())
+                .unwrap();
+            txn.commit().unwrap();
+        }
+        storage.drop_words().await.unwrap();
+        {
+            let txn = storage.db.begin_read().unwrap();
+            assert!(txn.open_table(WORDS).is_err());
+        }
+        // Files that never had words are fine too
+        storage.drop_words().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unindexed_documents() {
+        let (_dir, storage) = open_temp();
+        let pending = page("https://example.com/pending");
+        let indexed = page("https://example.com/indexed");
+        storage.try_commit(pending.clone()).await.unwrap();
+        storage.try_commit(indexed.clone()).await.unwrap();
+        storage.mark_indexed(&[indexed.get_id()]).await.unwrap();
+
+        // Committed pages are unindexed until their words are on disk
+        let unindexed = storage.unindexed_documents(10).await.unwrap();
+        assert_eq!(unindexed.len(), 1);
+        assert_eq!(unindexed[0].url, pending.url);
+        storage.mark_indexed(&[pending.get_id()]).await.unwrap();
+        assert!(storage.unindexed_documents(10).await.unwrap().is_empty());
+        // Replacing a page queues it to be indexed again
+        storage.replace_commit(pending.clone()).await.unwrap();
+        assert_eq!(storage.unindexed_documents(10).await.unwrap().len(), 1);
+    }
+}
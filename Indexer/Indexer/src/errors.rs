@@ -1,10 +1,169 @@
-use std::fmt;
+use std::{fmt, time::Duration};
 
-#[derive(Debug, Clone)]
-pub enum StateEvents{
-    TransactionError,
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::StorageError;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StateEvents {
     TransactionSuccess,
     InvalidExtension,
     UrlExists,
-    UrlError
-}
\ No newline at end of file
+    RobotsDisallowed,
+    SitemapExpanded,
+    DuplicateCanonical,
+    DocumentGone,
+    NotModified,
+    ContentUpdated,
+}
+
+// Everything that can stop a URL from being indexed
+#[derive(Debug)]
+pub enum IndexerError {
+    // Connection, DNS or TLS failure talking to the site
+    Network(String),
+    // The site did not answer within the client timeout
+    Timeout(String),
+    // The site answered with a status we do not index. `retry_after` is the
+    // server's Retry-After header, if it sent one.
+    HttpStatus {
+        status: u16,
+        retry_after: Option<Duration>,
+    },
+    // The site redirected more times than the configured hop limit
+    Redirect(String),
+    // The body could not be read or is not valid text
+    Decode(String),
+    // The URL or the page content could not be parsed
+    Parse(String),
+    // Reading from or writing to the database failed
+    Storage(StorageError),
+}
+
+impl IndexerError {
+    // Short name used when counting failures
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IndexerError::Network(_) => "network",
+            IndexerError::Timeout(_) => "timeout",
+            IndexerError::HttpStatus { .. } => "http_status",
+            IndexerError::Redirect(_) => "redirect",
+            IndexerError::Decode(_) => "decode",
+            IndexerError::Parse(_) => "parse",
+            IndexerError::Storage(_) => "storage",
+        }
+    }
+
+    pub fn from_status(status: StatusCode, headers: &HeaderMap) -> IndexerError {
+        IndexerError::HttpStatus {
+            status: status.as_u16(),
+            retry_after: headers
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after),
+        }
+    }
+
+    // Failures that may go away on their own, so the URL is worth another try
+    pub fn is_transient(&self) -> bool {
+        match self {
+            IndexerError::Network(_) | IndexerError::Timeout(_) => true,
+            IndexerError::HttpStatus { status, .. } => *status == 429 || *status >= 500,
+            IndexerError::Storage(e) => e.is_transient(),
+            IndexerError::Redirect(_) | IndexerError::Decode(_) | IndexerError::Parse(_) => false,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            IndexerError::HttpStatus { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+// Retry-After is either a number of seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(
+        at.duration_since(std::time::SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+impl fmt::Display for IndexerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexerError::Network(e) => write!(f, "network error: {}", e),
+            IndexerError::Timeout(e) => write!(f, "timed out: {}", e),
+            IndexerError::HttpStatus { status, .. } => write!(f, "HTTP status {}", status),
+            IndexerError::Redirect(e) => write!(f, "too many redirects: {}", e),
+            IndexerError::Decode(e) => write!(f, "failed to decode body: {}", e),
+            IndexerError::Parse(e) => write!(f, "failed to parse: {}", e),
+            IndexerError::Storage(e) => write!(f, "storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for IndexerError {}
+
+impl From<reqwest::Error> for IndexerError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            IndexerError::Timeout(e.to_string())
+        } else if e.is_redirect() {
+            IndexerError::Redirect(e.to_string())
+        } else if e.is_decode() || e.is_body() {
+            IndexerError::Decode(e.to_string())
+        } else if let Some(status) = e.status() {
+            IndexerError::HttpStatus {
+                status: status.as_u16(),
+                retry_after: None,
+            }
+        } else {
+            IndexerError::Network(e.to_string())
+        }
+    }
+}
+
+impl From<StorageError> for IndexerError {
+    fn from(e: StorageError) -> Self {
+        IndexerError::Storage(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transient_statuses() {
+        let status = |status| IndexerError::HttpStatus {
+            status,
+            retry_after: None,
+        };
+        assert!(status(429).is_transient());
+        assert!(status(503).is_transient());
+        assert!(!status(404).is_transient());
+        assert!(IndexerError::Timeout("slow".to_string()).is_transient());
+        assert!(!IndexerError::Parse("bad".to_string()).is_transient());
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
@@ -1,219 +1,1157 @@
 mod db;
+mod embedded;
 mod errors;
+mod memory;
+mod migrate;
 mod models;
+mod politeness;
+mod retry;
+mod robots;
+mod schedule;
+mod segments;
+mod storage;
 mod utils;
+use common::schema;
+use crawler::{CrawlConfig, Frontier, QueuedUrl, Sitemap, SitemapEntry};
 use dotenv::dotenv;
-use lol_html::{element, rewrite_str, text, HtmlRewriter, RewriteStrSettings, Settings};
 use reqwest::{
-    header::{HeaderMap, HeaderValue, CONTENT_TYPE, USER_AGENT},
-    Client,
+    header::{
+        HeaderMap, HeaderValue, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+    },
+    redirect::Policy,
+    Client, Response, StatusCode,
 };
 
 use std::{
+    collections::{BTreeMap, HashMap},
     fs::File,
-    io::{BufRead, BufReader, Write},
-    os::windows::process,
+    io::{BufRead, BufReader},
     path::Path,
     str,
-    sync::{atomic::AtomicI32, Arc},
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use utils::{is_binary_extension, is_text_content};
 
-use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use url::Url;
 use urlencoding::decode;
 
-use db::Database;
+use politeness::{HostLimiter, PolitenessConfig};
+use retry::RetryPolicy;
+use robots::RobotsCache;
+use schedule::RevisitPolicy;
+use segments::SegmentBuffer;
+use storage::{open_storage, Storage, StorageError};
 
 use crate::{
-    errors::StateEvents,
-    utils::{create_frequency, extract_structured_data},
+    errors::{IndexerError, StateEvents},
+    models::{DeadLetter, Document, FrontierState},
+    utils::{content_hash, create_frequency, env_or, extract_structured_data},
 };
-fn clean_html(body: String) -> String {
-    let mut output = String::new();
-    let mut rewriter = HtmlRewriter::new(
-        Settings {
-            element_content_handlers: vec![
-                element!("script", |el| {
-                    el.remove();
-                    Ok(())
-                }),
-                element!("svg", |el| {
-                    el.remove();
-                    Ok(())
-                }),
-                element!("style", |el| {
-                    el.remove();
-                    Ok(())
-                }),
-            ],
-            ..Settings::new()
-        },
-        |out: &[u8]| output.extend(str::from_utf8(out)),
-    );
-    rewriter.write(body.as_bytes()).unwrap();
-    rewriter.end().unwrap();
-    //println!("{}",&output[..100]);
-    output
-}
-fn extract_text(text: String) -> Vec<String> {
-    let text = text.trim();
-    if text.ends_with("</html>") {
-        let mut extracted_text = Vec::new();
-        rewrite_str(
-            text,
-            RewriteStrSettings {
-                element_content_handlers: vec![text!("*", |t| {
-                    let text_content = t.as_str().trim();
-                    if !text_content.is_empty() {
-                        extracted_text.push(text_content.to_string());
-                    }
-                    Ok(())
-                })],
-                ..RewriteStrSettings::new()
-            },
-        )
-        .unwrap();
-        extracted_text
-    } else {
-        text.lines()
-            .map(|line| line.trim().to_string())
-            .filter(|line| !line.is_empty())
-            .collect()
+
+// Any status outside 2xx is an error so that error pages are never indexed.
+// 429 and 5xx come back as transient and are retried.
+fn check_status(res: &Response) -> Result<(), IndexerError> {
+    let status = res.status();
+    if !status.is_success() {
+        return Err(IndexerError::from_status(status, res.headers()));
     }
+    Ok(())
+}
+
+// A successfully fetched page and where its redirects, if any, ended. A
+// 304 answer has an empty body.
+struct FetchedPage {
+    final_url: Url,
+    status: u16,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
 }
 
-async fn get_data(client: &Client, url: &str) -> String {
+// `previous` is the stored copy of the page, whose validators turn the
+// request into a conditional one
+async fn get_data(
+    client: &Client,
+    url: &str,
+    previous: Option<&Document>,
+) -> Result<FetchedPage, IndexerError> {
     let mut headers = HeaderMap::new();
-    headers.insert(USER_AGENT, HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36"));
-    let res = client.get(url).headers(headers).send().await;
-    let body: String;
-    match res {
-        Ok(val) => {
-            body = val.text().await.unwrap();
-        }
-        Err(e) => {
-            panic!("{}", e);
+    if let Some(previous) = previous {
+        let validators = [
+            (IF_NONE_MATCH, &previous.etag),
+            (IF_MODIFIED_SINCE, &previous.last_modified),
+        ];
+        for (name, value) in validators {
+            if let Some(value) = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+                headers.insert(name, value);
+            }
         }
     }
-    body
+    let res = client.get(url).headers(headers).send().await?;
+    let header = |name| {
+        res.headers()
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let not_modified = res.status() == StatusCode::NOT_MODIFIED;
+    if !not_modified {
+        check_status(&res)?;
+    }
+    let final_url = res.url().clone();
+    let status = res.status().as_u16();
+    let body = if not_modified {
+        String::new()
+    } else {
+        res.text().await?
+    };
+    Ok(FetchedPage {
+        final_url,
+        status,
+        etag,
+        last_modified,
+        body,
+    })
 }
 
-async fn check_content_type(client: &Client, url: &str) -> bool {
-    let mut headers = HeaderMap::new();
-    headers.insert(USER_AGENT, HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36"));
-    let head_response = client.head(url).headers(headers.clone()).send().await;
-    match head_response {
-        Ok(response) => {
-            if let Some(content_type) = response.headers().get(CONTENT_TYPE) {
-                let content_type_str = content_type.to_str().unwrap_or("").to_string();
-                if !is_text_content(&content_type_str) {
-                    println!(
-                        "Skipping non-text content: {} (Content-Type: {})",
-                        url, content_type_str
-                    );
-                    return false;
-                }
-            }
+async fn get_bytes(client: &Client, url: &str) -> Result<Vec<u8>, IndexerError> {
+    let res = client.get(url).send().await?;
+    check_status(&res)?;
+    Ok(res.bytes().await?.to_vec())
+}
 
-            // Check content length to avoid very large files
-            if let Some(content_length) = response.headers().get("content-length") {
-                if let Ok(length_str) = content_length.to_str() {
-                    if let Ok(length) = length_str.parse::<u64>() {
-                        const MAX_SIZE: u64 = 10 * 1024 * 1024; // 10MB limit
-                        if length > MAX_SIZE {
-                            println!("Skipping large file: {} ({} bytes)", url, length);
-                            return false;
-                        }
-                    }
+// Returns None when the URL turns out not to be a sitemap
+async fn fetch_sitemap(client: &Client, url: &Url) -> Result<Option<Sitemap>, IndexerError> {
+    let body = get_bytes(client, url.as_str()).await?;
+    let text = match crawler::decode_sitemap_body(&body) {
+        Some(text) => text,
+        None => return Ok(None),
+    };
+    Ok(crawler::parse_sitemap(&text, url))
+}
+
+async fn check_content_type(client: &Client, url: &str) -> Result<bool, IndexerError> {
+    let response = client.head(url).send().await?;
+    // Plenty of servers answer HEAD differently from GET, so a failed HEAD
+    // leaves the decision to the GET that follows
+    if !response.status().is_success() {
+        return Ok(true);
+    }
+    if let Some(content_type) = response.headers().get(CONTENT_TYPE) {
+        let content_type_str = content_type.to_str().unwrap_or("").to_string();
+        if !is_text_content(&content_type_str) {
+            println!(
+                "Skipping non-text content: {} (Content-Type: {})",
+                url, content_type_str
+            );
+            return Ok(false);
+        }
+    }
+
+    // Check content length to avoid very large files
+    if let Some(content_length) = response.headers().get("content-length") {
+        if let Ok(length_str) = content_length.to_str() {
+            if let Ok(length) = length_str.parse::<u64>() {
+                const MAX_SIZE: u64 = 10 * 1024 * 1024; // 10MB limit
+                if length > MAX_SIZE {
+                    println!("Skipping large file: {} ({} bytes)", url, length);
+                    return Ok(false);
                 }
             }
         }
-        Err(e) => {
-            println!("Failed to get HEAD response: {}", e);
-            return false;
+    }
+    Ok(true)
+}
+
+// New URLs a processed page contributes back to the frontier
+#[derive(Default)]
+struct Discovered {
+    links: Vec<Url>,
+    sitemap_entries: Vec<SitemapEntry>,
+}
+
+// How many URLs ended in each outcome or failure kind
+#[derive(Default)]
+struct Stats {
+    counts: Mutex<BTreeMap<String, usize>>,
+}
+
+impl Stats {
+    fn record(&self, key: &str) {
+        let mut counts = self.counts.lock().unwrap();
+        *counts.entry(key.to_string()).or_default() += 1;
+    }
+
+    fn report(&self) {
+        println!("Crawl summary:");
+        for (key, count) in self.counts.lock().unwrap().iter() {
+            println!("  {}: {}", key, count);
         }
     }
-    true
 }
 
-async fn process(url: String, db: Arc<Database>) -> StateEvents {
-    match db.url_exists(&url).await {
-        Ok(exists) => {
-            if exists {
-                return StateEvents::UrlExists;
+// Everything a fetch task needs, shared between all of them
+struct Context {
+    db: Arc<dyn Storage>,
+    client: Client,
+    strip_params: Vec<String>,
+    robots: RobotsCache,
+    limiter: HostLimiter,
+    retry: RetryPolicy,
+    stats: Stats,
+    // Pages processed at once
+    max_tasks: usize,
+    // Revisit pages that are already indexed instead of skipping them
+    recrawl: bool,
+    revisit: RevisitPolicy,
+    // Pages waiting to be written to the on-disk index
+    segments: SegmentBuffer,
+}
+
+async fn process(
+    url: String,
+    ctx: &Context,
+    discovered: &mut Discovered,
+) -> Result<StateEvents, IndexerError> {
+    // On a re-crawl the stored copy is fetched conditionally and only
+    // replaced when its content changed
+    let existing = if ctx.recrawl {
+        match ctx.db.find_document(&url).await? {
+            // Stored under another URL that redirects or points here
+            Some(document) if document.url != url => return Ok(StateEvents::UrlExists),
+            existing => existing,
+        }
+    } else if ctx.db.url_exists(&url).await? {
+        return Ok(StateEvents::UrlExists);
+    } else {
+        None
+    };
+    let parsed_url = Url::parse(&url).map_err(|e| IndexerError::Parse(e.to_string()))?;
+
+    let robots = ctx.robots.rules_for(&parsed_url).await;
+    // The first page seen from a host also queues the sitemaps its
+    // robots.txt lists
+    for sitemap in ctx.robots.take_sitemaps(&parsed_url).await {
+        discovered.sitemap_entries.push(SitemapEntry {
+            loc: sitemap,
+            lastmod: None,
+        });
+    }
+    if !ctx.robots.is_allowed(&parsed_url).await {
+        return Ok(StateEvents::RobotsDisallowed);
+    }
+    // Every request to the host waits for its own turn
+    let permit = || ctx.limiter.acquire(&parsed_url, robots.crawl_delay());
+
+    let client = &ctx.client;
+
+    // Sitemaps are expanded into the frontier instead of being indexed. This
+    // runs before the extension check since `.xml.gz` sitemaps are common.
+    if existing.is_none() && crawler::is_sitemap_url(&parsed_url) {
+        let _permit = permit().await;
+        if let Some(sitemap) = fetch_sitemap(client, &parsed_url).await? {
+            discovered.sitemap_entries.extend(sitemap.into_entries());
+            return Ok(StateEvents::SitemapExpanded);
+        }
+    }
+
+    if is_binary_extension(&url) {
+        return Ok(StateEvents::InvalidExtension);
+    }
+    let html = {
+        let _permit = permit().await;
+        check_content_type(client, &url).await?
+    };
+    if !html {
+        return Ok(StateEvents::InvalidExtension);
+    }
+
+    let fetched = {
+        let _permit = permit().await;
+        get_data(client, &url, existing.as_ref()).await
+    };
+    let page = match fetched {
+        Ok(page) => page,
+        // The page was removed on purpose, so it leaves the index as well
+        Err(IndexerError::HttpStatus { status: 410, .. }) => {
+            let removed = ctx.db.remove_document(&url).await?;
+            if let Some(existing) = &existing {
+                ctx.segments.remove(existing.get_id());
             }
+            println!("{} is gone, removed {} documents", url, removed);
+            return Ok(StateEvents::DocumentGone);
         }
-        Err(e) => {
-            println!("Error checking URL existence: {}", e);
-            return StateEvents::UrlError;
+        Err(e) => return Err(e),
+    };
+    if page.status == StatusCode::NOT_MODIFIED.as_u16() {
+        if let Some(existing) = existing {
+            record_unchanged(ctx, existing, page.etag, page.last_modified).await?;
+        }
+        return Ok(StateEvents::NotModified);
+    }
+
+    // After a redirect the page is indexed once, under the URL it was asked
+    // for, and its links are resolved against where it actually lives
+    let final_url = crawler::normalize_url(&page.final_url, &ctx.strip_params);
+    let final_str = match decode(final_url.as_str()) {
+        Ok(decoded) => decoded.into_owned(),
+        Err(_) => final_url.to_string(),
+    };
+    if existing.is_none() && final_str != url && ctx.db.url_exists(&final_str).await? {
+        return Ok(StateEvents::UrlExists);
+    }
+    let parsed_url = page.final_url;
+
+    // XML served from a URL that does not look like a sitemap may still be one
+    if let Some(sitemap) = crawler::parse_sitemap(&page.body, &parsed_url) {
+        discovered.sitemap_entries.extend(sitemap.into_entries());
+        return Ok(StateEvents::SitemapExpanded);
+    }
+
+    // Collect outgoing links before the page is consumed by extraction
+    discovered.links = crawler::extract_links(&page.body, &parsed_url);
+
+    let mut documents = extract_structured_data(page.body, url)?;
+    documents.http_status = page.status;
+    documents.final_url = final_str;
+    documents.etag = page.etag;
+    documents.last_modified = page.last_modified;
+    documents.fetched_at = Some(mongodb::bson::DateTime::now());
+
+    // Pages that declare another URL as canonical are only indexed once,
+    // under whichever URL reaches the index first
+    let declared = documents.canonical_url.trim().to_string();
+    documents.canonical_url = String::new();
+    if !declared.is_empty() {
+        if let Ok(canonical) = parsed_url.join(&declared) {
+            let canonical = crawler::normalize_url(&canonical, &ctx.strip_params);
+            documents.canonical_url = match decode(canonical.as_str()) {
+                Ok(decoded) => decoded.into_owned(),
+                Err(_) => canonical.to_string(),
+            };
         }
     }
-    let client = Client::new();
+    documents.content_hash = content_hash(&documents);
 
-    if is_binary_extension(&url) || !check_content_type(&client, &url).await {
-        return StateEvents::InvalidExtension;
+    if let Some(existing) = existing {
+        if existing.content_hash == documents.content_hash {
+            record_unchanged(ctx, existing, documents.etag, documents.last_modified).await?;
+            return Ok(StateEvents::NotModified);
+        }
+        documents._id = existing.get_id();
+        documents.fetch_count = existing.fetch_count;
+        documents.change_count = existing.change_count;
+        documents.revisit_secs = existing.revisit_secs;
+        ctx.revisit.record_visit(&mut documents, true);
+        ctx.db.replace_commit(documents.clone()).await?;
+        index_words(ctx, &documents).await?;
+        return Ok(StateEvents::ContentUpdated);
     }
 
-    let page = get_data(&client, &url).await;
+    if !documents.canonical_url.is_empty()
+        && documents.canonical_url != documents.url
+        && ctx.db.url_exists(&documents.canonical_url).await?
+    {
+        return Ok(StateEvents::DuplicateCanonical);
+    }
 
-    let documents = extract_structured_data(page, url);
     //println!("{}",output);
-    let words = create_frequency(&documents);
+    ctx.revisit.record_visit(&mut documents, true);
+    ctx.db.try_commit(documents.clone()).await?;
+    index_words(ctx, &documents).await?;
+    Ok(StateEvents::TransactionSuccess)
+}
+// Queues the words of a stored page for the next segment. The page stays
+// `unindexed` until a flush writes them, so a crash or a failed write in
+// between leaves it to `reindex_unindexed` on the next start.
+async fn index_words(ctx: &Context, document: &Document) -> Result<(), StorageError> {
+    let written = ctx.segments.add(document, create_frequency(document))?;
+    ctx.db.mark_indexed(&written).await
+}
+async fn flush_segments(ctx: &Context) -> Result<(), StorageError> {
+    let written = ctx.segments.flush()?;
+    ctx.db.mark_indexed(&written).await
+}
+// Writes the words of every page stored without them reaching a segment.
+// Returns how many pages were indexed.
+async fn reindex_unindexed(ctx: &Context) -> Result<usize, StorageError> {
+    let mut reindexed = 0;
+    loop {
+        let batch = ctx.db.unindexed_documents(100).await?;
+        if batch.is_empty() {
+            return Ok(reindexed);
+        }
+        for document in &batch {
+            ctx.segments.add(document, create_frequency(document))?;
+        }
+        ctx.segments.flush()?;
+        let ids: Vec<_> = batch.iter().map(|document| document.get_id()).collect();
+        ctx.db.mark_indexed(&ids).await?;
+        reindexed += batch.len();
+    }
+}
+// Indexes what an earlier run stored but did not get to write to a segment
+async fn recover_unindexed(ctx: &Context) -> Result<(), ()> {
+    match reindex_unindexed(ctx).await {
+        Ok(0) => Ok(()),
+        Ok(count) => {
+            println!("Indexed {} pages an earlier run left unindexed", count);
+            Ok(())
+        }
+        Err(e) => {
+            println!("Failed to index pages an earlier run left unindexed: {}", e);
+            Err(())
+        }
+    }
+}
+// Stores what a revisit learned about a page whose content did not change
+async fn record_unchanged(
+    ctx: &Context,
+    mut existing: Document,
+    etag: Option<String>,
+    last_modified: Option<String>,
+) -> Result<(), IndexerError> {
+    // A 304 may leave out the validators, in which case the old ones hold
+    if etag.is_some() {
+        existing.etag = etag;
+    }
+    if last_modified.is_some() {
+        existing.last_modified = last_modified;
+    }
+    existing.fetched_at = Some(mongodb::bson::DateTime::now());
+    ctx.revisit.record_visit(&mut existing, false);
+    ctx.db.touch_document(&existing).await?;
+    Ok(())
+}
+async fn process_url(url: String, depth: u32, ctx: Arc<Context>) -> Discovered {
+    if let Err(e) = ctx
+        .db
+        .frontier_mark(&url, FrontierState::InFlight, None, None)
+        .await
+    {
+        println!("Failed to update frontier for {}: {}", url, e);
+    }
+    let decoded = match decode(url.as_str()) {
+        Ok(decoded) => decoded.into_owned(),
+        Err(_) => url.clone(),
+    };
+    let mut discovered = Discovered::default();
+    let mut attempt = 1;
+    let result = loop {
+        let result = process(decoded.clone(), &ctx, &mut discovered).await;
+        match &result {
+            Err(e) if e.is_transient() && attempt < ctx.retry.max_attempts => {
+                let delay = ctx.retry.delay(attempt, e.retry_after());
+                println!(
+                    "Attempt {} for {} failed ({}), retrying in {}ms",
+                    attempt,
+                    url,
+                    e,
+                    delay.as_millis()
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            _ => break result,
+        }
+    };
 
-    if db.try_commit(documents, words).await {
-        return StateEvents::TransactionSuccess;
+    let (state, reason, error) = match &result {
+        Ok(StateEvents::TransactionSuccess) | Ok(StateEvents::SitemapExpanded) => {
+            (FrontierState::Done, None, None)
+        }
+        Ok(event) => (FrontierState::for_event(event), Some(event.clone()), None),
+        Err(e) => (FrontierState::Failed, None, Some(e.to_string())),
+    };
+    if let Err(e) = ctx.db.frontier_mark(&url, state, reason, error).await {
+        println!("Failed to update frontier for {}: {}", url, e);
+    }
+
+    match result {
+        Ok(event) => {
+            ctx.stats.record(&format!("{:?}", event));
+            match event {
+                StateEvents::TransactionSuccess => {
+                    println!("Transaction successful");
+                }
+                StateEvents::InvalidExtension => {
+                    println!("Invalid extension or content type, skipping URL");
+                }
+                StateEvents::UrlExists => {
+                    println!("URL already exists");
+                }
+                StateEvents::RobotsDisallowed => {
+                    println!("Disallowed by robots.txt, skipping URL");
+                }
+                StateEvents::DuplicateCanonical => {
+                    println!("Canonical URL already indexed, skipping duplicate");
+                }
+                StateEvents::DocumentGone => {
+                    println!("Page is gone, removed from the index");
+                }
+                StateEvents::NotModified => {
+                    println!("Page unchanged since the last visit");
+                }
+                StateEvents::ContentUpdated => {
+                    println!("Page changed, document and words replaced");
+                }
+                StateEvents::SitemapExpanded => {
+                    println!(
+                        "Sitemap expanded into {} URLs",
+                        discovered.sitemap_entries.len()
+                    );
+                }
+            }
+        }
+        Err(e) => {
+            ctx.stats.record(&format!("error:{}", e.kind()));
+            println!("Failed to index {} after {} attempts: {}", url, attempt, e);
+            let letter = DeadLetter::new(
+                url.clone(),
+                depth,
+                e.kind().to_string(),
+                e.to_string(),
+                attempt,
+            );
+            if let Err(e) = ctx.db.dead_letter_add(letter).await {
+                println!("Failed to record dead letter for {}: {}", url, e);
+            }
+        }
     }
-    StateEvents::TransactionError
+    discovered
 }
-async fn process_url(url: String, db: Arc<Database>) {
-    match process(decode(url.as_str()).expect("UTF-8").into_owned(), db).await {
-        StateEvents::TransactionSuccess => {
-            println!("Transaction successful");
+async fn persist_queued(db: &dyn Storage, queued: QueuedUrl) {
+    if let Err(e) = db
+        .frontier_enqueue(&queued.url, queued.depth, queued.lastmod)
+        .await
+    {
+        println!("Failed to persist {} to the frontier: {}", queued.url, e);
+    }
+}
+// One-off maintenance commands, run instead of a crawl
+async fn run_command(command: &str) -> Result<(), ()> {
+    match command {
+        "requeue-dead-letters" => {
+            let db = open_storage().await;
+            match db.dead_letters_requeue().await {
+                Ok(count) => {
+                    println!("Requeued {} dead-lettered URLs", count);
+                    Ok(())
+                }
+                Err(e) => {
+                    println!("Failed to requeue dead letters: {}", e);
+                    Err(())
+                }
+            }
+        }
+        "migrate" => {
+            let db = open_storage().await;
+            let batch_size = env_or("MIGRATION_BATCH_SIZE", 100i64).max(1);
+            let segments = SegmentBuffer::from_env();
+            match migrate::run(db.as_ref(), &segments, batch_size).await {
+                Ok(count) => {
+                    println!(
+                        "Migrated {} documents to schema version {}",
+                        count,
+                        common::SCHEMA_VERSION
+                    );
+                    Ok(())
+                }
+                Err(e) => {
+                    println!("Migration stopped: {}", e);
+                    Err(())
+                }
+            }
+        }
+        // Only meaningful for the MongoDB backend
+        "index-status" => {
+            let db = common::db::connect().await;
+            match schema::index_status(&db, &db::indexes()).await {
+                Ok(status) => {
+                    println!("Index status:");
+                    for (index, present) in &status {
+                        println!(
+                            "  {}.{}{}: {}",
+                            index.collection,
+                            index.name(),
+                            if index.unique { " (unique)" } else { "" },
+                            if *present { "present" } else { "missing" }
+                        );
+                    }
+                    let missing = status.iter().filter(|(_, present)| !present).count();
+                    if missing > 0 {
+                        println!(
+                            "{} indexes missing, they are created on the next start",
+                            missing
+                        );
+                    }
+                    Ok(())
+                }
+                Err(e) => {
+                    println!("Failed to read indexes: {}", e);
+                    Err(())
+                }
+            }
+        }
+        "force-merge" => {
+            let dir = common::segment::index_dir();
+            match common::merge::force_merge(&dir) {
+                Ok(()) => {
+                    println!("Merged the segments in {} into one", dir.display());
+                    Ok(())
+                }
+                Err(e) => {
+                    println!("Failed to merge segments: {}", e);
+                    Err(())
+                }
+            }
+        }
+        _ => {
+            println!("Unknown command: {}", command);
+            println!(
+                "Available commands: recrawl, schedule, requeue-dead-letters, migrate, index-status, force-merge"
+            );
+            Err(())
+        }
+    }
+}
+// Warns when the stored data was written with a different schema than the
+// one this build writes
+async fn check_schema(db: &dyn Storage) {
+    let stored = db.schema_version().await.ok().flatten();
+    if stored.is_some_and(|version| version > common::SCHEMA_VERSION) {
+        println!(
+            "Stored data uses schema version {}, newer than {} this build writes",
+            stored.unwrap_or_default(),
+            common::SCHEMA_VERSION
+        );
+        return;
+    }
+    let outdated = db
+        .outdated_documents(common::SCHEMA_VERSION, 1)
+        .await
+        .is_ok_and(|documents| !documents.is_empty());
+    if outdated {
+        println!("Some documents use an older schema, run `migrate` to upgrade them");
+    }
+}
+// Everything a crawl shares between its fetch tasks, configured from the
+// environment
+async fn build_context(config: &CrawlConfig, recrawl: bool) -> Arc<Context> {
+    let politeness = PolitenessConfig::from_env();
+    println!(
+        "Fetching with {} connections, {} per host, {}ms apart, {} pages at a time",
+        politeness.max_concurrency,
+        politeness.per_host_concurrency,
+        politeness.min_delay.as_millis(),
+        politeness.max_tasks
+    );
+    let timeout = env_or("FETCH_TIMEOUT_SECS", 30);
+    let max_redirects = env_or("FETCH_MAX_REDIRECTS", 10);
+    let contact = env_or("CRAWLER_CONTACT", String::new());
+    let client = match Client::builder()
+        .user_agent(robots::user_agent(&contact))
+        .timeout(Duration::from_secs(timeout))
+        .redirect(Policy::limited(max_redirects))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            panic!("{}", e);
         }
-        StateEvents::InvalidExtension => {
-            println!("Invalid extension or content type, skipping URL");
+    };
+    Arc::new(Context {
+        db: open_storage().await,
+        robots: RobotsCache::new(client.clone()),
+        client,
+        strip_params: config.strip_params.clone(),
+        max_tasks: politeness.max_tasks,
+        limiter: HostLimiter::new(politeness),
+        retry: RetryPolicy::from_env(),
+        stats: Stats::default(),
+        recrawl,
+        revisit: RevisitPolicy::from_env(),
+        segments: SegmentBuffer::from_env(),
+    })
+}
+// Keeps the index fresh by revisiting pages as they come due, taking at
+// most `per_host_limit` pages of any one host per round
+async fn run_schedule() -> Result<(), ()> {
+    let config = CrawlConfig::from_env();
+    let ctx = build_context(&config, true).await;
+    recover_unindexed(&ctx).await?;
+    let policy = ctx.revisit.clone();
+    println!(
+        "Scheduling revisits every {}s to {}s, {} pages per host per round",
+        policy.min_interval.as_secs(),
+        policy.max_interval.as_secs(),
+        policy.per_host_limit
+    );
+    loop {
+        let due = match ctx.db.due_urls(policy.batch_size).await {
+            Ok(due) => due,
+            Err(e) => {
+                println!("Failed to load due pages: {}", e);
+                Vec::new()
+            }
+        };
+
+        let mut per_host: HashMap<String, usize> = HashMap::new();
+        let mut picked = Vec::new();
+        for url in due {
+            let host = match Url::parse(&url) {
+                Ok(parsed) => parsed.host_str().unwrap_or_default().to_string(),
+                Err(_) => String::new(),
+            };
+            let count = per_host.entry(host).or_default();
+            if *count < policy.per_host_limit {
+                *count += 1;
+                picked.push(url);
+            }
         }
-        StateEvents::TransactionError => {
-            println!("Transaction failed");
+        if picked.is_empty() {
+            tokio::time::sleep(policy.poll_interval).await;
+            continue;
         }
-        StateEvents::UrlExists => {
-            println!("URL already exists");
+
+        // Pages that fail are pushed back by the minimum interval so they do
+        // not come due again straight away; a successful visit overwrites it
+        if let Err(e) = ctx.db.postpone_revisits(&picked, policy.min_interval).await {
+            println!("Failed to postpone due pages: {}", e);
         }
-        StateEvents::UrlError => {
-            println!("Error checking URL");
+        println!("Revisiting {} due pages", picked.len());
+        let mut tasks = JoinSet::new();
+        let mut picked = picked.into_iter();
+        loop {
+            while tasks.len() < ctx.max_tasks {
+                let Some(url) = picked.next() else {
+                    break;
+                };
+                let ctx_clone = Arc::clone(&ctx);
+                tasks.spawn(async move {
+                    process_url(url, 0, ctx_clone).await;
+                });
+            }
+            match tasks.join_next().await {
+                Some(Err(e)) => println!("Revisit task failed: {}", e),
+                Some(Ok(())) => {}
+                None => break,
+            }
+        }
+        // Pages left pending are written with the next round
+        if let Err(e) = flush_segments(&ctx).await {
+            println!("Failed to write segment: {}", e);
         }
+        ctx.stats.report();
     }
 }
 #[tokio::main]
 async fn main() -> Result<(), ()> {
-    dotenv().ok().expect("Env error");
+    dotenv().ok();
+    // `recrawl` revisits every indexed page once and `schedule` keeps
+    // revisiting them as they come due; anything else is a maintenance command
+    let recrawl = match std::env::args().nth(1).as_deref() {
+        Some("recrawl") => true,
+        Some("schedule") => return run_schedule().await,
+        Some(command) => return run_command(command).await,
+        None => false,
+    };
     let path = Path::new("../urls.txt");
-    let pool = Arc::new(Semaphore::new(10));
 
-    let db = Arc::new(Database::new().await);
-    let pbar=AtomicI32::new(0);
+    let config = CrawlConfig::from_env();
+    let ctx = build_context(&config, recrawl).await;
+    let db = ctx.db.as_ref();
+    check_schema(db).await;
+    recover_unindexed(&ctx).await?;
+    println!(
+        "Crawling to depth {} with a budget of {} pages",
+        config.max_depth, config.max_pages
+    );
+    let mut frontier = Frontier::new(config);
 
-    let file = match File::open(path) {
-        Ok(file) => file,
-        Err(e) => {
-            panic!("{}", e);
+    if recrawl {
+        // Every stored document is seeded again; the frontier of earlier runs
+        // would mark them all as seen, so it is not loaded
+        match db.document_urls().await {
+            Ok(urls) => {
+                println!("Re-crawling {} indexed pages", urls.len());
+                for url in urls {
+                    if let Some(seed) = frontier.push_seed(&url) {
+                        persist_queued(db, seed).await;
+                    }
+                }
+            }
+            Err(e) => {
+                println!("Failed to load indexed pages: {}", e);
+                return Err(());
+            }
         }
+    } else {
+        // Pick up where the last run stopped: queued URLs go back in the queue,
+        // everything else has already been handled and is only marked as seen.
+        match db.frontier_resume().await {
+            Ok(entries) => {
+                let mut resumed = 0;
+                for entry in entries {
+                    if entry.state != FrontierState::Queued {
+                        frontier.mark_seen(&entry.url);
+                    } else if frontier.requeue(QueuedUrl {
+                        url: entry.url,
+                        depth: entry.depth,
+                        lastmod: entry.lastmod,
+                    }) {
+                        resumed += 1;
+                    }
+                }
+                println!("Resuming with {} queued URLs", resumed);
+            }
+            Err(e) => {
+                println!("Failed to load frontier, starting fresh: {}", e);
+            }
+        }
+
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) => {
+                panic!("{}", e);
+            }
+        };
+        let urls = BufReader::new(file).lines();
+        for url in urls.map_while(Result::ok) {
+            if let Some(seed) = frontier.push_seed(&url) {
+                persist_queued(db, seed).await;
+            }
+        }
+    }
+
+    // Pages are indexed as they come off the frontier and the links found on
+    // each one are fed back in, until nothing is queued or in flight. Only
+    // `max_tasks` pages are taken off at a time; the rest wait in the
+    // frontier.
+    let mut tasks = JoinSet::new();
+    loop {
+        while tasks.len() < ctx.max_tasks {
+            let Some(next) = frontier.pop() else {
+                break;
+            };
+            let ctx_clone = Arc::clone(&ctx);
+
+            tasks.spawn(async move {
+                let discovered = process_url(next.url.clone(), next.depth, ctx_clone).await;
+                (next, discovered)
+            });
+        }
+        match tasks.join_next().await {
+            Some(Ok((parent, discovered))) => {
+                if let Ok(parent_url) = Url::parse(&parent.url) {
+                    let mut queued =
+                        frontier.push_links(&parent_url, parent.depth, discovered.links);
+                    queued.extend(frontier.push_sitemap(
+                        &parent_url,
+                        parent.depth,
+                        discovered.sitemap_entries,
+                    ));
+                    for entry in queued {
+                        persist_queued(db, entry).await;
+                    }
+                }
+            }
+            Some(Err(e)) => {
+                println!("Indexing task failed: {}", e);
+            }
+            None => break,
+        }
+    }
+    let flushed = flush_segments(&ctx).await;
+    println!("Crawl finished, {} pages admitted", frontier.admitted());
+    ctx.stats.report();
+    flushed.map_err(|e| {
+        println!("Failed to write segment, the next run indexes it: {}", e);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
     };
-    let mut tasks = Vec::new();
-    let urls = BufReader::new(file).lines();
-    for url in urls.flatten() {
-        let db_clone = Arc::clone(&db);
-        let pool_clone = Arc::clone(&pool);
-
-        let task = tokio::spawn(async move {
-            let _permit = pool_clone.acquire().await.unwrap();
-            process_url(url, db_clone).await;
+
+    use common::segment::Index;
+
+    use super::*;
+    use crate::memory::MemoryStorage;
+
+    // A canned answer the test server gives for one path
+    #[derive(Clone)]
+    struct Route {
+        status: u16,
+        etag: Option<String>,
+        body: String,
+    }
+
+    type Routes = Arc<Mutex<HashMap<String, Route>>>;
+
+    // Answers requests on a local port from `routes`, with a 404 for every
+    // other path and a 304 when If-None-Match carries the route's ETag.
+    // Returns the base URL.
+    async fn serve(routes: Routes) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let routes = Arc::clone(&routes);
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request).to_string();
+                    let mut lines = request.lines();
+                    let mut first = lines.next().unwrap_or_default().split_whitespace();
+                    let method = first.next().unwrap_or_default().to_string();
+                    let path = first.next().unwrap_or("/").to_string();
+                    let if_none_match = lines.find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("if-none-match")
+                            .then(|| value.trim().to_string())
+                    });
+
+                    let route = routes.lock().unwrap().get(&path).cloned();
+                    let route = route.unwrap_or(Route {
+                        status: 404,
+                        etag: None,
+                        body: String::new(),
+                    });
+                    let (status, body) = match (&route.etag, &if_none_match) {
+                        (Some(etag), Some(sent)) if etag == sent => (304, String::new()),
+                        _ => (route.status, route.body),
+                    };
+                    let mut head = format!(
+                        "HTTP/1.1 {} Test\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        status,
+                        body.len()
+                    );
+                    if let Some(etag) = route.etag {
+                        head.push_str(&format!("ETag: {}\r\n", etag));
+                    }
+                    head.push_str("\r\n");
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    if method != "HEAD" {
+                        let _ = socket.write_all(body.as_bytes()).await;
+                    }
+                    let _ = socket.shutdown().await;
+                });
+            }
         });
-        tasks.push(task);
+        format!("http://{}", addr)
     }
-    for task in tasks {
-        let _ = task.await;
+
+    fn page(title: &str, text: &str) -> String {
+        format!(
+            "<html><head><title>{}</title><meta name=\"description\" content=\"A test page\"></head>\
+             <body><p>{}</p><a href=\"/other\">Other</a></body></html>",
+            title, text
+        )
+    }
+
+    fn route(status: u16, etag: Option<&str>, body: String) -> Route {
+        Route {
+            status,
+            etag: etag.map(|etag| etag.to_string()),
+            body,
+        }
+    }
+
+    fn test_context(db: Arc<dyn Storage>, recrawl: bool, index_dir: &Path) -> Context {
+        let politeness = PolitenessConfig {
+            min_delay: Duration::ZERO,
+            ..PolitenessConfig::default()
+        };
+        Context {
+            db,
+            client: Client::new(),
+            strip_params: Vec::new(),
+            robots: RobotsCache::new(Client::new()),
+            max_tasks: politeness.max_tasks,
+            limiter: HostLimiter::new(politeness),
+            retry: RetryPolicy::default(),
+            stats: Stats::default(),
+            recrawl,
+            revisit: RevisitPolicy::default(),
+            segments: SegmentBuffer::new(index_dir.to_path_buf(), 1000),
+        }
+    }
+
+    async fn crawl_once(url: &str, ctx: &Context) -> StateEvents {
+        let mut discovered = Discovered::default();
+        process(url.to_string(), ctx, &mut discovered)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_process_indexes_page() {
+        let routes = Routes::default();
+        let body = page("Ferris", "Ferris the crab loves rustaceans");
+        routes
+            .lock()
+            .unwrap()
+            .insert("/page".to_string(), route(200, None, body));
+        let base = serve(Arc::clone(&routes)).await;
+        let memory = Arc::new(MemoryStorage::new());
+        let index_dir = tempfile::tempdir().unwrap();
+        let ctx = test_context(memory.clone(), false, index_dir.path());
+        let url = format!("{}/page", base);
+
+        let mut discovered = Discovered::default();
+        let event = process(url.clone(), &ctx, &mut discovered).await.unwrap();
+        assert_eq!(event, StateEvents::TransactionSuccess);
+        assert_eq!(discovered.links.len(), 1);
+        assert!(discovered.links[0].as_str().ends_with("/other"));
+
+        let document = memory.find_document(&url).await.unwrap().unwrap();
+        assert_eq!(document.title, "Ferris");
+        assert_eq!(document.http_status, 200);
+        assert_eq!(document.fetch_count, 1);
+        assert!(document.unindexed);
+
+        // Its words land in the on-disk index once the buffer is flushed
+        flush_segments(&ctx).await.unwrap();
+        let document = memory.find_document(&url).await.unwrap().unwrap();
+        assert!(!document.unindexed);
+        let index = Index::open(index_dir.path()).unwrap();
+        let count = |word: &str| {
+            let postings = index.postings(word).unwrap();
+            postings.first().map(|(_, posting)| posting.count)
+        };
+        // Title words are boosted
+        assert_eq!(count("ferris"), Some(51));
+        assert_eq!(count("rustaceans"), Some(1));
+        assert_eq!(count("the"), None);
+        let postings = index.postings("crab").unwrap();
+        assert_eq!(postings.len(), 1);
+        let (stored, posting) = &postings[0];
+        assert_eq!(posting.positions.body, [1]);
+        assert_eq!(stored.id, document.get_id());
+        assert_eq!(stored.title, "Ferris");
+
+        let event = process(url, &ctx, &mut Discovered::default()).await;
+        assert_eq!(event.unwrap(), StateEvents::UrlExists);
+    }
+
+    #[tokio::test]
+    async fn test_process_skips_error_pages() {
+        let base = serve(Routes::default()).await;
+        let memory = Arc::new(MemoryStorage::new());
+        let index_dir = tempfile::tempdir().unwrap();
+        let ctx = test_context(memory.clone(), false, index_dir.path());
+        let url = format!("{}/missing", base);
+
+        let error = process(url.clone(), &ctx, &mut Discovered::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            IndexerError::HttpStatus { status: 404, .. }
+        ));
+        assert!(!error.is_transient());
+        assert!(!memory.url_exists(&url).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_recrawl_updates_and_removes_pages() {
+        let routes = Routes::default();
+        let set_route = |route: Route| {
+            routes.lock().unwrap().insert("/page".to_string(), route);
+        };
+        set_route(route(
+            200,
+            Some("\"v1\""),
+            page("Crabs", "Crabs walk sideways"),
+        ));
+        let base = serve(Arc::clone(&routes)).await;
+        let memory = Arc::new(MemoryStorage::new());
+        let index_dir = tempfile::tempdir().unwrap();
+        let ctx = test_context(memory.clone(), true, index_dir.path());
+        let url = format!("{}/page", base);
+
+        assert_eq!(
+            crawl_once(&url, &ctx).await,
+            StateEvents::TransactionSuccess
+        );
+        let first = memory.find_document(&url).await.unwrap().unwrap();
+
+        // The stored ETag makes the revisit conditional
+        assert_eq!(crawl_once(&url, &ctx).await, StateEvents::NotModified);
+        let document = memory.find_document(&url).await.unwrap().unwrap();
+        assert_eq!(document.fetch_count, 2);
+        assert_eq!(document.change_count, 0);
+
+        set_route(route(200, Some("\"v2\""), page("Crabs", "Crabs molt")));
+        assert_eq!(crawl_once(&url, &ctx).await, StateEvents::ContentUpdated);
+        let document = memory.find_document(&url).await.unwrap().unwrap();
+        assert_eq!(document.get_id(), first.get_id());
+        assert_eq!(document.change_count, 1);
+        flush_segments(&ctx).await.unwrap();
+        let index = Index::open(index_dir.path()).unwrap();
+        assert_eq!(index.postings("molt").unwrap().len(), 1);
+        assert!(index.postings("sideways").unwrap().is_empty());
+
+        set_route(route(410, None, String::new()));
+        assert_eq!(crawl_once(&url, &ctx).await, StateEvents::DocumentGone);
+        assert!(!memory.url_exists(&url).await.unwrap());
+
+        // The removal hides the page in the segment written before it
+        flush_segments(&ctx).await.unwrap();
+        let index = Index::open(index_dir.path()).unwrap();
+        assert_eq!(index.segments().len(), 2);
+        assert_eq!(index.doc_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pages_left_unindexed_are_indexed_on_start() {
+        let routes = Routes::default();
+        let body = page("Ferris", "Ferris the crab loves rustaceans");
+        routes
+            .lock()
+            .unwrap()
+            .insert("/page".to_string(), route(200, None, body));
+        let base = serve(Arc::clone(&routes)).await;
+        let memory = Arc::new(MemoryStorage::new());
+        let url = format!("{}/page", base);
+
+        // The segment cannot be written where a file is in the way
+        let blocked = tempfile::NamedTempFile::new().unwrap();
+        let ctx = test_context(memory.clone(), false, blocked.path());
+        assert_eq!(
+            crawl_once(&url, &ctx).await,
+            StateEvents::TransactionSuccess
+        );
+        assert!(flush_segments(&ctx).await.is_err());
+        let document = memory.find_document(&url).await.unwrap().unwrap();
+        assert!(document.unindexed);
+
+        // A later run finds the page through its flag, not its URL
+        let index_dir = tempfile::tempdir().unwrap();
+        let ctx = test_context(memory.clone(), false, index_dir.path());
+        assert_eq!(crawl_once(&url, &ctx).await, StateEvents::UrlExists);
+        assert_eq!(reindex_unindexed(&ctx).await.unwrap(), 1);
+        let index = Index::open(index_dir.path()).unwrap();
+        assert_eq!(index.postings("crab").unwrap().len(), 1);
+        let document = memory.find_document(&url).await.unwrap().unwrap();
+        assert!(!document.unindexed);
+        assert_eq!(reindex_unindexed(&ctx).await.unwrap(), 0);
     }
-    Ok(())
 }
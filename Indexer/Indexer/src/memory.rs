@@ -0,0 +1,269 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use mongodb::bson::{oid::ObjectId, DateTime};
+
+use crate::{
+    errors::StateEvents,
+    models::{DeadLetter, Document, FrontierEntry, FrontierState},
+    storage::{scheme_variants, Storage, StorageError},
+};
+
+#[derive(Default)]
+struct Tables {
+    documents: BTreeMap<ObjectId, Document>,
+    frontier: BTreeMap<String, FrontierEntry>,
+    dead_letters: BTreeMap<String, DeadLetter>,
+    schema_version: Option<u32>,
+}
+
+// Storage that lives only as long as the process, for tests and dry runs.
+// Lookups match the MongoDB backend.
+#[derive(Default)]
+pub struct MemoryStorage {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap()
+    }
+}
+
+impl Tables {
+    fn matching(&self, url: &str) -> Vec<ObjectId> {
+        let variants = scheme_variants(url);
+        self.documents
+            .values()
+            .filter(|document| {
+                [&document.url, &document.final_url, &document.canonical_url]
+                    .into_iter()
+                    .any(|alias| variants.contains(alias))
+            })
+            .map(|document| document.get_id())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn url_exists(&self, url: &str) -> Result<bool, StorageError> {
+        Ok(!self.tables().matching(url).is_empty())
+    }
+
+    async fn find_document(&self, url: &str) -> Result<Option<Document>, StorageError> {
+        let tables = self.tables();
+        let id = tables.matching(url).into_iter().next();
+        Ok(id.and_then(|id| tables.documents.get(&id).cloned()))
+    }
+
+    async fn document_urls(&self) -> Result<Vec<String>, StorageError> {
+        let tables = self.tables();
+        Ok(tables.documents.values().map(|d| d.url.clone()).collect())
+    }
+
+    async fn due_urls(&self, limit: i64) -> Result<Vec<String>, StorageError> {
+        let now = DateTime::now();
+        let tables = self.tables();
+        let mut due: Vec<&Document> = tables
+            .documents
+            .values()
+            .filter(|document| document.next_fetch_at.is_none_or(|at| at <= now))
+            .collect();
+        due.sort_by_key(|document| document.next_fetch_at);
+        Ok(due
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .map(|document| document.url.clone())
+            .collect())
+    }
+
+    async fn postpone_revisits(
+        &self,
+        urls: &[String],
+        delay: Duration,
+    ) -> Result<(), StorageError> {
+        let until =
+            DateTime::from_millis(DateTime::now().timestamp_millis() + delay.as_millis() as i64);
+        for document in self.tables().documents.values_mut() {
+            if urls.contains(&document.url) {
+                document.next_fetch_at = Some(until);
+            }
+        }
+        Ok(())
+    }
+
+    async fn touch_document(&self, document: &Document) -> Result<(), StorageError> {
+        if let Some(stored) = self.tables().documents.get_mut(&document.get_id()) {
+            stored.etag = document.etag.clone();
+            stored.last_modified = document.last_modified.clone();
+            stored.fetched_at = document.fetched_at;
+            stored.fetch_count = document.fetch_count;
+            stored.change_count = document.change_count;
+            stored.revisit_secs = document.revisit_secs;
+            stored.next_fetch_at = document.next_fetch_at;
+        }
+        Ok(())
+    }
+
+    async fn try_commit(&self, document: Document) -> Result<(), StorageError> {
+        let document = Document {
+            unindexed: true,
+            ..document
+        };
+        let mut tables = self.tables();
+        let id = document.get_id();
+        if tables.documents.contains_key(&id) {
+            return Err(StorageError::Embedded(format!(
+                "document {} already exists",
+                id
+            )));
+        }
+        tables.documents.insert(id, document);
+        Ok(())
+    }
+
+    async fn replace_commit(&self, document: Document) -> Result<(), StorageError> {
+        let document = Document {
+            unindexed: true,
+            ..document
+        };
+        let mut tables = self.tables();
+        tables.documents.insert(document.get_id(), document);
+        Ok(())
+    }
+
+    async fn mark_indexed(&self, ids: &[ObjectId]) -> Result<(), StorageError> {
+        let mut tables = self.tables();
+        for id in ids {
+            if let Some(document) = tables.documents.get_mut(id) {
+                document.unindexed = false;
+            }
+        }
+        Ok(())
+    }
+
+    async fn unindexed_documents(&self, limit: i64) -> Result<Vec<Document>, StorageError> {
+        let tables = self.tables();
+        Ok(tables
+            .documents
+            .values()
+            .filter(|document| document.unindexed)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn remove_document(&self, url: &str) -> Result<u64, StorageError> {
+        let mut tables = self.tables();
+        let ids = tables.matching(url);
+        for id in &ids {
+            tables.documents.remove(id);
+        }
+        Ok(ids.len() as u64)
+    }
+
+    async fn frontier_enqueue(
+        &self,
+        url: &str,
+        depth: u32,
+        lastmod: Option<String>,
+    ) -> Result<bool, StorageError> {
+        let mut tables = self.tables();
+        if tables.frontier.contains_key(url) {
+            return Ok(false);
+        }
+        let entry = FrontierEntry::new(url.to_string(), depth, lastmod);
+        tables.frontier.insert(url.to_string(), entry);
+        Ok(true)
+    }
+
+    async fn frontier_mark(
+        &self,
+        url: &str,
+        state: FrontierState,
+        reason: Option<StateEvents>,
+        error: Option<String>,
+    ) -> Result<(), StorageError> {
+        if let Some(entry) = self.tables().frontier.get_mut(url) {
+            entry.state = state;
+            entry.reason = reason;
+            entry.error = error;
+            entry.updated_at = DateTime::now();
+        }
+        Ok(())
+    }
+
+    async fn frontier_resume(&self) -> Result<Vec<FrontierEntry>, StorageError> {
+        let mut tables = self.tables();
+        for entry in tables.frontier.values_mut() {
+            if entry.state == FrontierState::InFlight {
+                entry.state = FrontierState::Queued;
+                entry.updated_at = DateTime::now();
+            }
+        }
+        let mut entries: Vec<FrontierEntry> = tables.frontier.values().cloned().collect();
+        entries.sort_by_key(|entry| entry.depth);
+        Ok(entries)
+    }
+
+    async fn dead_letter_add(&self, letter: DeadLetter) -> Result<(), StorageError> {
+        self.tables()
+            .dead_letters
+            .insert(letter.url.clone(), letter);
+        Ok(())
+    }
+
+    async fn dead_letters_requeue(&self) -> Result<usize, StorageError> {
+        let mut tables = self.tables();
+        let letters = std::mem::take(&mut tables.dead_letters);
+        for letter in letters.values() {
+            let entry = tables
+                .frontier
+                .entry(letter.url.clone())
+                .or_insert_with(|| FrontierEntry::new(letter.url.clone(), letter.depth, None));
+            entry.state = FrontierState::Queued;
+            entry.reason = None;
+            entry.error = None;
+            entry.updated_at = DateTime::now();
+        }
+        Ok(letters.len())
+    }
+
+    async fn schema_version(&self) -> Result<Option<u32>, StorageError> {
+        Ok(self.tables().schema_version)
+    }
+
+    async fn set_schema_version(&self, version: u32) -> Result<(), StorageError> {
+        self.tables().schema_version = Some(version);
+        Ok(())
+    }
+
+    // Nothing here ever held words
+    async fn drop_words(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn outdated_documents(
+        &self,
+        version: u32,
+        limit: i64,
+    ) -> Result<Vec<Document>, StorageError> {
+        let tables = self.tables();
+        Ok(tables
+            .documents
+            .values()
+            .filter(|document| document.schema_version < version)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,125 @@
+use common::SCHEMA_VERSION;
+
+use crate::{
+    models::Document,
+    segments::SegmentBuffer,
+    storage::{Storage, StorageError},
+    utils::{content_hash, create_frequency},
+};
+
+// One step per schema version, taking a document from `from` to `from + 1`
+type Step = fn(&mut Document);
+
+const STEPS: &[(u32, Step)] = &[
+    (0, backfill_fetch_fields),
+    (1, record_positions),
+    (2, move_words_to_segments),
+];
+
+// Documents indexed before re-crawls were tracked have no content hash and
+// no record of having been fetched
+fn backfill_fetch_fields(document: &mut Document) {
+    if document.content_hash.is_empty() {
+        document.content_hash = content_hash(document);
+    }
+    if document.fetch_count == 0 {
+        document.fetch_count = 1;
+    }
+}
+
+// Words written before positions were recorded have none. The document
+// itself is unchanged; `run` rebuilds its words from the stored text.
+fn record_positions(_document: &mut Document) {}
+
+// Words used to be stored as one row per word and document. `run` writes
+// them to the index segments instead and drops the rows once every
+// document is across.
+fn move_words_to_segments(_document: &mut Document) {}
+
+// Brings a document up to SCHEMA_VERSION one step at a time
+pub fn upgrade(document: &mut Document) {
+    for (from, step) in STEPS {
+        if document.schema_version == *from {
+            step(document);
+            document.schema_version = from + 1;
+        }
+    }
+    document.schema_version = SCHEMA_VERSION;
+}
+
+// Upgrades every outdated document `batch_size` at a time. Words are
+// derived again from the stored text and each batch is written to a new
+// segment before its documents are marked as upgraded, so the migration is
+// safe to interrupt: the next run carries on with what is left.
+pub async fn run(
+    db: &dyn Storage,
+    segments: &SegmentBuffer,
+    batch_size: i64,
+) -> Result<usize, StorageError> {
+    let mut migrated = 0;
+    loop {
+        let mut batch = db.outdated_documents(SCHEMA_VERSION, batch_size).await?;
+        if batch.is_empty() {
+            break;
+        }
+        for document in &mut batch {
+            upgrade(document);
+            segments.add(document, create_frequency(document))?;
+        }
+        segments.flush()?;
+        let ids: Vec<_> = batch.iter().map(|document| document.get_id()).collect();
+        for document in batch {
+            db.replace_commit(document).await?;
+            migrated += 1;
+        }
+        db.mark_indexed(&ids).await?;
+        println!("Migrated {} documents", migrated);
+    }
+    db.drop_words().await?;
+    db.set_schema_version(SCHEMA_VERSION).await?;
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use common::segment::Index;
+
+    use super::*;
+    use crate::memory::MemoryStorage;
+
+    #[tokio::test]
+    async fn test_run_upgrades_old_documents() {
+        let db = MemoryStorage::new();
+        let index_dir = tempfile::tempdir().unwrap();
+        let segments = SegmentBuffer::new(index_dir.path().to_path_buf(), 1000);
+        let mut old = Document {
+            url: "https://example.com/".to_string(),
+            full_text: vec!["crab".to_string(), "crab".to_string()],
+            schema_version: 0,
+            ..Document::default()
+        };
+        old.content_hash = String::new();
+        db.try_commit(old.clone()).await.unwrap();
+        db.try_commit(Document::default()).await.unwrap();
+
+        assert_eq!(db.schema_version().await.unwrap(), None);
+        assert_eq!(run(&db, &segments, 1).await.unwrap(), 1);
+        assert_eq!(db.schema_version().await.unwrap(), Some(SCHEMA_VERSION));
+
+        let migrated = db.find_document(&old.url).await.unwrap().unwrap();
+        assert_eq!(migrated.schema_version, SCHEMA_VERSION);
+        assert_eq!(migrated.content_hash, content_hash(&old));
+        assert_eq!(migrated.fetch_count, 1);
+        // Its words were rebuilt from the stored text into a segment
+        let index = Index::open(index_dir.path()).unwrap();
+        let crab = index.postings("crab").unwrap();
+        assert_eq!(crab.len(), 1);
+        assert_eq!(crab[0].0.id, old.get_id());
+        assert_eq!(crab[0].1.count, 2);
+        assert_eq!(crab[0].1.positions.body, [0, 1]);
+
+        // Nothing is left to do the second time
+        assert_eq!(run(&db, &segments, 1).await.unwrap(), 0);
+        assert_eq!(Index::open(index_dir.path()).unwrap().segments().len(), 1);
+    }
+}
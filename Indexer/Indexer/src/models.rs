@@ -1,101 +1,92 @@
-use std::fmt;
-
-use mongodb::bson::{oid::ObjectId};
+use mongodb::bson::{oid::ObjectId, DateTime};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Words {
-    pub _id: ObjectId,
-    pub document: ObjectId,
-    pub word: String,
-    pub count: i32,
-}
-impl Default for Words {
-    fn default() -> Self {
-        Words {
-            _id: ObjectId::new(),
-            document: ObjectId::new(),
-            word: "default".to_string(),
-            count: 0,
-        }
-    }
-    
+use crate::errors::StateEvents;
+
+pub use common::models::{Document, Field, Positions, Words};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FrontierState {
+    Queued,
+    InFlight,
+    Done,
+    Failed,
+    Skipped,
 }
 
-impl Words {
-    pub fn new(document: ObjectId, word: String, count: i32) -> Self {
-        Words {
-            _id: ObjectId::new(),
-            document,
-            word,
-            count,
+impl FrontierState {
+    // Where a URL ends up in the frontier once `process` has finished with it
+    pub fn for_event(event: &StateEvents) -> FrontierState {
+        match event {
+            StateEvents::TransactionSuccess
+            | StateEvents::SitemapExpanded
+            | StateEvents::DocumentGone
+            | StateEvents::NotModified
+            | StateEvents::ContentUpdated => FrontierState::Done,
+            StateEvents::InvalidExtension
+            | StateEvents::UrlExists
+            | StateEvents::RobotsDisallowed
+            | StateEvents::DuplicateCanonical => FrontierState::Skipped,
         }
     }
-    
-
-   
 }
 
-#[derive(Serialize, Deserialize, Debug,Clone)]
-pub struct Document {
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FrontierEntry {
     pub _id: ObjectId,
     pub url: String,
-     pub title: String,
-     pub description: String,
-    pub canonical_url: String,
-    pub summary_text: String,
-     pub full_text: Vec<String>,
+    pub depth: u32,
+    // Last modification date from the sitemap that listed this URL
+    #[serde(default)]
+    pub lastmod: Option<String>,
+    pub state: FrontierState,
+    pub reason: Option<StateEvents>,
+    // Last error for URLs in the failed state
+    #[serde(default)]
+    pub error: Option<String>,
+    pub updated_at: DateTime,
 }
 
-impl  Default for Document {
-    fn default() -> Self {
-        Document {
+impl FrontierEntry {
+    pub fn new(url: String, depth: u32, lastmod: Option<String>) -> Self {
+        FrontierEntry {
             _id: ObjectId::new(),
-            url: "Testing Test ting 124".to_string(),
-            title: "Testing Title".to_string(),
-            description: "Testing Description".to_string(),
-            canonical_url: "https://example.com/testing".to_string(),
-            summary_text: "Testing Summary".to_string(),
-            full_text: vec!["Testing full text".to_string()],
+            url,
+            depth,
+            lastmod,
+            state: FrontierState::Queued,
+            reason: None,
+            error: None,
+            updated_at: DateTime::now(),
         }
     }
-    
 }
-impl Document {
-    pub fn new(url: String, title: String, description: String, canonical_url: String, summary_text: String, full_text: Vec<String>) -> Self {
-        Document {
+
+// A URL that kept failing after every retry, kept so it can be looked at and
+// queued again later
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeadLetter {
+    pub _id: ObjectId,
+    pub url: String,
+    pub depth: u32,
+    // IndexerError::kind of the last failure
+    pub kind: String,
+    pub error: String,
+    pub attempts: u32,
+    pub failed_at: DateTime,
+}
+
+impl DeadLetter {
+    pub fn new(url: String, depth: u32, kind: String, error: String, attempts: u32) -> Self {
+        DeadLetter {
             _id: ObjectId::new(),
             url,
-            title,
-            description,
-            canonical_url,
-            summary_text,
-            full_text,
+            depth,
+            kind,
+            error,
+            attempts,
+            failed_at: DateTime::now(),
         }
     }
-     pub fn get_full_text(&self) -> &Vec<String> {
-        &self.full_text
-    }
-    pub fn get_title(&self) -> String {
-        self.title.clone()
-    }
-    pub fn get_description(&self) -> String {
-        self.description.clone()
-    }
-    pub fn get_id(&self)->ObjectId{
-        self._id
-    }
-    
 }
-impl fmt::Display for Document {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "Document {{")?;
-        writeln!(f, "  title: {:?}", self.title)?;
-        writeln!(f, "  description: {:?}", self.description)?;
-        writeln!(f, "  canonical_url: {:?}", self.canonical_url)?;
-        writeln!(f, "  summary_text: \"{}\"", self.summary_text)?;
-        writeln!(f, "  full_text: {:?} words", self.full_text.len())?;
-        write!(f, "}}")
-    }
-}
-
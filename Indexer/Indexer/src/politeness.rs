@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+use url::Url;
+
+use crate::{robots::MAX_CRAWL_DELAY, utils::env_or};
+
+#[derive(Debug, Clone)]
+pub struct PolitenessConfig {
+    // Requests in flight across all hosts
+    pub max_concurrency: usize,
+    // Pages being worked on at once, including their storage lookups,
+    // robots.txt fetches and parsing. Kept above `max_concurrency` so that
+    // pages waiting on a busy host leave room for other hosts.
+    pub max_tasks: usize,
+    // Requests in flight to any single host
+    pub per_host_concurrency: usize,
+    // Minimum gap between two requests to the same host when its
+    // robots.txt has no Crawl-delay
+    pub min_delay: Duration,
+}
+
+impl Default for PolitenessConfig {
+    fn default() -> Self {
+        PolitenessConfig {
+            max_concurrency: 10,
+            max_tasks: 40,
+            per_host_concurrency: 2,
+            min_delay: Duration::from_millis(1000),
+        }
+    }
+}
+
+impl PolitenessConfig {
+    pub fn from_env() -> PolitenessConfig {
+        let default = PolitenessConfig::default();
+        let min_delay_ms = env_or("CRAWL_MIN_DELAY_MS", default.min_delay.as_millis() as u64);
+        PolitenessConfig {
+            max_concurrency: env_or("CRAWL_CONCURRENCY", default.max_concurrency).max(1),
+            max_tasks: env_or("CRAWL_MAX_TASKS", default.max_tasks).max(1),
+            per_host_concurrency: env_or(
+                "CRAWL_PER_HOST_CONCURRENCY",
+                default.per_host_concurrency,
+            )
+            .max(1),
+            min_delay: Duration::from_millis(min_delay_ms),
+        }
+    }
+}
+
+struct HostSlot {
+    permits: Arc<Semaphore>,
+    next_request: Mutex<Instant>,
+}
+
+// Held for the duration of a page fetch. Dropping it frees both the host
+// slot and the global slot.
+pub struct FetchPermit {
+    _host: OwnedSemaphorePermit,
+    _global: OwnedSemaphorePermit,
+}
+
+// Decides when a request to a host may go out: at most
+// `per_host_concurrency` at a time, spaced at least the host's delay apart,
+// and never more than `max_concurrency` overall.
+pub struct HostLimiter {
+    config: PolitenessConfig,
+    global: Arc<Semaphore>,
+    hosts: Mutex<HashMap<String, Arc<HostSlot>>>,
+}
+
+impl HostLimiter {
+    pub fn new(config: PolitenessConfig) -> HostLimiter {
+        HostLimiter {
+            global: Arc::new(Semaphore::new(config.max_concurrency)),
+            config,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Taken before every request. `crawl_delay` comes from the host's
+    // robots.txt and replaces the configured minimum delay when present.
+    pub async fn acquire(&self, url: &Url, crawl_delay: Option<Duration>) -> FetchPermit {
+        let slot = self.slot(url);
+
+        // The host slot is taken before the global one so that a long queue
+        // for one busy host never holds global slots other hosts could use.
+        let host = Arc::clone(&slot.permits).acquire_owned().await.unwrap();
+
+        let delay = crawl_delay.unwrap_or(self.config.min_delay);
+        let wait_until = {
+            let mut next_request = slot.next_request.lock().unwrap();
+            let start = (*next_request).max(Instant::now());
+            // A delay too far out for an Instant is as good as the longest
+            // one robots.txt may ask for
+            *next_request = start.checked_add(delay).unwrap_or(start + MAX_CRAWL_DELAY);
+            start
+        };
+        tokio::time::sleep_until(wait_until).await;
+
+        let global = Arc::clone(&self.global).acquire_owned().await.unwrap();
+        FetchPermit {
+            _host: host,
+            _global: global,
+        }
+    }
+
+    fn slot(&self, url: &Url) -> Arc<HostSlot> {
+        let key = match (url.host_str(), url.port_or_known_default()) {
+            (Some(host), Some(port)) => format!("{}:{}", host.to_ascii_lowercase(), port),
+            (Some(host), None) => host.to_ascii_lowercase(),
+            _ => String::new(),
+        };
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts
+            .entry(key)
+            .or_insert_with(|| {
+                Arc::new(HostSlot {
+                    permits: Arc::new(Semaphore::new(self.config.per_host_concurrency)),
+                    next_request: Mutex::new(Instant::now()),
+                })
+            })
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_to_one_host_are_spaced() {
+        let limiter = HostLimiter::new(PolitenessConfig {
+            max_concurrency: 10,
+            max_tasks: 10,
+            per_host_concurrency: 10,
+            min_delay: Duration::from_secs(1),
+        });
+        let page = Url::parse("https://example.com/a").unwrap();
+        let other = Url::parse("https://other.org/a").unwrap();
+
+        let start = Instant::now();
+        let _first = limiter.acquire(&page, None).await;
+        let _other = limiter.acquire(&other, None).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        let _second = limiter.acquire(&page, None).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        // A Crawl-delay from robots.txt replaces the default gap
+        let _third = limiter.acquire(&page, Some(Duration::from_secs(5))).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+        let _fourth = limiter.acquire(&page, None).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(7));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_huge_delays_do_not_overflow() {
+        let limiter = HostLimiter::new(PolitenessConfig {
+            min_delay: Duration::MAX,
+            ..PolitenessConfig::default()
+        });
+        let page = Url::parse("https://example.com/a").unwrap();
+
+        let start = Instant::now();
+        drop(limiter.acquire(&page, None).await);
+        drop(limiter.acquire(&page, Some(Duration::MAX)).await);
+        assert_eq!(start.elapsed(), MAX_CRAWL_DELAY);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_per_host_concurrency() {
+        let limiter = Arc::new(HostLimiter::new(PolitenessConfig {
+            max_concurrency: 10,
+            max_tasks: 10,
+            per_host_concurrency: 1,
+            min_delay: Duration::ZERO,
+        }));
+        let page = Url::parse("https://example.com/a").unwrap();
+
+        let held = limiter.acquire(&page, None).await;
+        let waiting = {
+            let limiter = Arc::clone(&limiter);
+            let page = page.clone();
+            tokio::spawn(async move { limiter.acquire(&page, None).await })
+        };
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        drop(held);
+        waiting.await.unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use crate::utils::env_or;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    // Total tries for a URL, the first one included
+    pub max_attempts: u32,
+    // Wait after the first failure, doubled after every further one
+    pub base_delay: Duration,
+    // Upper bound on any single wait, Retry-After included
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: env_or("RETRY_MAX_ATTEMPTS", default.max_attempts).max(1),
+            base_delay: Duration::from_millis(env_or(
+                "RETRY_BASE_DELAY_MS",
+                default.base_delay.as_millis() as u64,
+            )),
+            max_delay: Duration::from_millis(env_or(
+                "RETRY_MAX_DELAY_MS",
+                default.max_delay.as_millis() as u64,
+            )),
+        }
+    }
+
+    // How long to wait after `attempt` (counting from 1) failed. The
+    // exponential delay is jittered between half and all of itself so that
+    // URLs failing together do not retry together, and a server's
+    // Retry-After is honoured when it asks for longer.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let factor = 1u32 << attempt.saturating_sub(1).min(16);
+        let backoff = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let half = backoff / 2;
+        let jittered = half + half.mul_f64(rand::random::<f64>());
+        match retry_after {
+            Some(after) => after.max(jittered).min(self.max_delay),
+            None => jittered,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+        };
+        for (attempt, full) in [(1, 1), (2, 2), (3, 4), (4, 8), (5, 10), (40, 10)] {
+            let full = Duration::from_secs(full);
+            let delay = policy.delay(attempt, None);
+            assert!(delay >= full / 2 && delay <= full, "attempt {}", attempt);
+        }
+    }
+
+    #[test]
+    fn test_retry_after_is_honoured() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        };
+        let delay = policy.delay(1, Some(Duration::from_secs(20)));
+        assert_eq!(delay, Duration::from_secs(20));
+        let delay = policy.delay(1, Some(Duration::from_secs(3600)));
+        assert_eq!(delay, Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use reqwest::Client;
+use tokio::sync::OnceCell;
+use url::Url;
+
+// Product token matched against `User-agent` lines in robots.txt
+pub const ROBOTS_USER_AGENT: &str = "SearchEngineBot";
+
+// Longest Crawl-delay we honour. Sites asking for more are still crawled,
+// just this far apart.
+pub const MAX_CRAWL_DELAY: Duration = Duration::from_secs(60);
+
+// The User-Agent every request is sent with, so sites see the same crawler
+// their robots.txt rules are applied to. `contact` tells site owners where
+// to reach us and is left out when empty.
+pub fn user_agent(contact: &str) -> String {
+    let agent = format!("{}/{}", ROBOTS_USER_AGENT, env!("CARGO_PKG_VERSION"));
+    if contact.is_empty() {
+        agent
+    } else {
+        format!("{} (+{})", agent, contact)
+    }
+}
+
+// Whether a `User-agent` line names our product token. Any version after
+// a `/` is ignored and case does not matter.
+fn names_agent(line: &str, user_agent: &str) -> bool {
+    let token = line.split('/').next().unwrap_or_default().trim();
+    token.eq_ignore_ascii_case(user_agent)
+}
+
+// A Crawl-delay value in seconds, capped at MAX_CRAWL_DELAY. Negative and
+// unparsable values are ignored.
+fn parse_crawl_delay(value: &str) -> Option<Duration> {
+    let secs = value.parse::<f64>().ok()?;
+    if secs.is_nan() || secs < 0.0 {
+        return None;
+    }
+    // Too large for a Duration is also too large to honour
+    let delay = Duration::try_from_secs_f64(secs).unwrap_or(MAX_CRAWL_DELAY);
+    Some(delay.min(MAX_CRAWL_DELAY))
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Robots {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+    sitemaps: Vec<String>,
+}
+
+impl Robots {
+    pub fn allow_all() -> Robots {
+        Robots::default()
+    }
+
+    pub fn disallow_all() -> Robots {
+        Robots {
+            rules: vec![Rule {
+                allow: false,
+                pattern: "/".to_string(),
+            }],
+            crawl_delay: None,
+            sitemaps: Vec::new(),
+        }
+    }
+
+    // Parses a robots.txt body keeping only the groups that apply to
+    // `user_agent`. Groups naming the agent win over the `*` group, which is
+    // only used when there are none.
+    pub fn parse(body: &str, user_agent: &str) -> Robots {
+        let mut specific = Robots::default();
+        let mut wildcard = Robots::default();
+        let mut found_specific = false;
+        let mut sitemaps = Vec::new();
+
+        // User agents of the group being read, and whether its rules started
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+
+        for line in body.lines() {
+            let line = match line.find('#') {
+                Some(idx) => &line[..idx],
+                None => line,
+            };
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+
+            // Sitemap lines stand outside of any group
+            if key == "sitemap" {
+                if !value.is_empty() {
+                    sitemaps.push(value.to_string());
+                }
+                continue;
+            }
+            if key == "user-agent" {
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_ascii_lowercase());
+                continue;
+            }
+            if agents.is_empty() {
+                continue;
+            }
+            in_rules = true;
+
+            let applies_specific = agents.iter().any(|agent| names_agent(agent, user_agent));
+            let applies_wildcard = agents.iter().any(|agent| agent == "*");
+            if applies_specific {
+                found_specific = true;
+            }
+
+            for (applies, target) in [
+                (applies_specific, &mut specific),
+                (applies_wildcard, &mut wildcard),
+            ] {
+                if !applies {
+                    continue;
+                }
+                match key.as_str() {
+                    // An empty Disallow means everything is allowed
+                    "allow" | "disallow" if !value.is_empty() => {
+                        target.rules.push(Rule {
+                            allow: key == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                    "crawl-delay" => {
+                        if let Some(delay) = parse_crawl_delay(value) {
+                            target.crawl_delay = Some(delay);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut robots = if found_specific { specific } else { wildcard };
+        robots.sitemaps = sitemaps;
+        robots
+    }
+
+    // The most specific (longest) matching rule decides; Allow wins a tie.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        let mut best: Option<&Rule> = None;
+        for rule in &self.rules {
+            if !pattern_matches(&rule.pattern, path) {
+                continue;
+            }
+            best = match best {
+                Some(current)
+                    if current.pattern.len() > rule.pattern.len()
+                        || (current.pattern.len() == rule.pattern.len() && current.allow) =>
+                {
+                    Some(current)
+                }
+                _ => Some(rule),
+            };
+        }
+        best.map(|rule| rule.allow).unwrap_or(true)
+    }
+
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+
+    pub fn sitemaps(&self) -> &[String] {
+        &self.sitemaps
+    }
+}
+
+// robots.txt patterns are path prefixes where `*` matches any run of
+// characters and a trailing `$` anchors the end of the path.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(stripped) => (stripped, true),
+        None => (pattern, false),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+
+    let mut rest = match path.strip_prefix(parts[0]) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    for (i, part) in parts.iter().enumerate().skip(1) {
+        let last = i == parts.len() - 1;
+        if last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+// Fetches robots.txt once per origin and keeps the parsed rules for the
+// rest of the run.
+pub struct RobotsCache {
+    client: Client,
+    hosts: Mutex<HashMap<String, Arc<OnceCell<Arc<Robots>>>>>,
+    // Origins whose robots.txt sitemaps were already handed out
+    announced: Mutex<HashSet<String>>,
+}
+
+impl RobotsCache {
+    // `client` is the one pages are fetched with, so robots.txt is asked for
+    // under the same User-Agent
+    pub fn new(client: Client) -> RobotsCache {
+        RobotsCache {
+            client,
+            hosts: Mutex::new(HashMap::new()),
+            announced: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub async fn is_allowed(&self, url: &Url) -> bool {
+        let robots = self.rules_for(url).await;
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        robots.is_allowed(&path)
+    }
+
+    // The sitemaps listed in the origin's robots.txt, returned only the
+    // first time they are asked for so they are queued once per run.
+    pub async fn take_sitemaps(&self, url: &Url) -> Vec<Url> {
+        let origin = url.origin().ascii_serialization();
+        if !self.announced.lock().unwrap().insert(origin) {
+            return Vec::new();
+        }
+        let robots = self.rules_for(url).await;
+        robots
+            .sitemaps()
+            .iter()
+            .filter_map(|sitemap| url.join(sitemap).ok())
+            .collect()
+    }
+
+    pub async fn rules_for(&self, url: &Url) -> Arc<Robots> {
+        let host = self.host(url);
+        host.get_or_init(|| async { Arc::new(self.fetch(url).await) })
+            .await
+            .clone()
+    }
+
+    fn host(&self, url: &Url) -> Arc<OnceCell<Arc<Robots>>> {
+        let origin = url.origin().ascii_serialization();
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts.entry(origin).or_default().clone()
+    }
+
+    async fn fetch(&self, url: &Url) -> Robots {
+        let robots_url = match url.join("/robots.txt") {
+            Ok(robots_url) => robots_url,
+            Err(_) => return Robots::allow_all(),
+        };
+        let response = self.client.get(robots_url.as_str()).send().await;
+
+        // A missing robots.txt allows everything, while a server error means
+        // the site may be in trouble so nothing is fetched from it.
+        match response {
+            Ok(response) if response.status().is_success() => match response.text().await {
+                Ok(body) => Robots::parse(&body, ROBOTS_USER_AGENT),
+                Err(e) => {
+                    println!("Failed to read {}: {}", robots_url, e);
+                    Robots::allow_all()
+                }
+            },
+            Ok(response) if response.status().is_server_error() => {
+                println!(
+                    "{} returned {}, treating host as disallowed",
+                    robots_url,
+                    response.status()
+                );
+                Robots::disallow_all()
+            }
+            Ok(_) => Robots::allow_all(),
+            Err(e) => {
+                println!("Failed to fetch {}: {}", robots_url, e);
+                Robots::allow_all()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "
+# comment line
+User-agent: *
+Disallow: /private/
+Allow: /private/public.html
+Disallow: /*.pdf$
+Crawl-delay: 2
+
+User-agent: SearchEngineBot
+User-agent: OtherBot
+Disallow: /no-bots
+Crawl-delay: 0.5
+
+Sitemap: https://example.com/sitemap.xml
+";
+
+    #[test]
+    fn test_specific_group_wins() {
+        let robots = Robots::parse(ROBOTS, ROBOTS_USER_AGENT);
+        assert!(!robots.is_allowed("/no-bots/page"));
+        assert!(robots.is_allowed("/private/secret"));
+        assert_eq!(robots.crawl_delay(), Some(Duration::from_millis(500)));
+        assert_eq!(robots.sitemaps(), ["https://example.com/sitemap.xml"]);
+    }
+
+    #[test]
+    fn test_wildcard_group() {
+        let robots = Robots::parse(ROBOTS, "SomeoneElse");
+        assert!(!robots.is_allowed("/private/secret"));
+        assert!(robots.is_allowed("/private/public.html"));
+        assert!(!robots.is_allowed("/files/report.pdf"));
+        assert!(robots.is_allowed("/files/report.pdf?download=1"));
+        assert!(robots.is_allowed("/"));
+        assert_eq!(robots.crawl_delay(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_crawl_delay_is_capped() {
+        let delay = |value: &str| {
+            let body = format!("User-agent: *\nCrawl-delay: {}\n", value);
+            Robots::parse(&body, ROBOTS_USER_AGENT).crawl_delay()
+        };
+        assert_eq!(delay("1e30"), Some(MAX_CRAWL_DELAY));
+        assert_eq!(delay("inf"), Some(MAX_CRAWL_DELAY));
+        assert_eq!(delay("3600"), Some(MAX_CRAWL_DELAY));
+        assert_eq!(delay("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(delay("-1"), None);
+        assert_eq!(delay("NaN"), None);
+        assert_eq!(delay("soon"), None);
+    }
+
+    #[test]
+    fn test_group_must_name_the_agent_exactly() {
+        let body = "
+User-agent: Bot
+User-agent: Search
+Disallow: /
+
+User-agent: searchenginebot/2.0
+Disallow: /mine
+
+User-agent: *
+Disallow: /everyone
+";
+        let robots = Robots::parse(body, ROBOTS_USER_AGENT);
+        assert!(robots.is_allowed("/page"));
+        assert!(!robots.is_allowed("/mine"));
+        // Only the `*` group applies when no group names us
+        let robots = Robots::parse("User-agent: Bot\nDisallow: /\n", ROBOTS_USER_AGENT);
+        assert!(robots.is_allowed("/page"));
+    }
+
+    #[test]
+    fn test_user_agent() {
+        let version = env!("CARGO_PKG_VERSION");
+        assert_eq!(user_agent(""), format!("SearchEngineBot/{}", version));
+        assert_eq!(
+            user_agent("https://example.com/bot"),
+            format!("SearchEngineBot/{} (+https://example.com/bot)", version)
+        );
+    }
+
+    #[test]
+    fn test_empty_disallow_allows_everything() {
+        let robots = Robots::parse("User-agent: *\nDisallow:\n", ROBOTS_USER_AGENT);
+        assert!(robots.is_allowed("/anything"));
+        assert!(!Robots::disallow_all().is_allowed("/anything"));
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use mongodb::bson::DateTime;
+
+use crate::{models::Document, utils::env_or};
+
+#[derive(Debug, Clone)]
+pub struct RevisitPolicy {
+    // Gap before the first revisit of a newly indexed page
+    pub initial_interval: Duration,
+    // Bounds for pages that change all the time and pages that never do
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+    // How long the scheduler sleeps when nothing is due
+    pub poll_interval: Duration,
+    // Pages revisited per host in one scheduler round
+    pub per_host_limit: usize,
+    // Due pages loaded per scheduler round
+    pub batch_size: i64,
+}
+
+impl Default for RevisitPolicy {
+    fn default() -> Self {
+        RevisitPolicy {
+            initial_interval: Duration::from_secs(24 * 60 * 60),
+            min_interval: Duration::from_secs(60 * 60),
+            max_interval: Duration::from_secs(30 * 24 * 60 * 60),
+            poll_interval: Duration::from_secs(60),
+            per_host_limit: 20,
+            batch_size: 500,
+        }
+    }
+}
+
+impl RevisitPolicy {
+    pub fn from_env() -> RevisitPolicy {
+        let default = RevisitPolicy::default();
+        let secs = |key, default: Duration| Duration::from_secs(env_or(key, default.as_secs()));
+        RevisitPolicy {
+            initial_interval: secs("REVISIT_INITIAL_SECS", default.initial_interval),
+            min_interval: secs("REVISIT_MIN_SECS", default.min_interval),
+            max_interval: secs("REVISIT_MAX_SECS", default.max_interval),
+            poll_interval: secs("SCHEDULE_POLL_SECS", default.poll_interval),
+            per_host_limit: env_or("SCHEDULE_PER_HOST_LIMIT", default.per_host_limit).max(1),
+            batch_size: env_or("SCHEDULE_BATCH_SIZE", default.batch_size).max(1),
+        }
+    }
+
+    // Halves the interval after a visit that found new content and grows it
+    // by half after one that did not, so every page settles near the rate
+    // it actually changes at.
+    pub fn next_interval(&self, current: Duration, changed: bool) -> Duration {
+        let next = if changed {
+            current / 2
+        } else {
+            current.mul_f64(1.5)
+        };
+        next.clamp(self.min_interval, self.max_interval)
+    }
+
+    // Updates the fetch statistics and next revisit time of a document that
+    // was just fetched. `changed` is whether its content hash moved.
+    pub fn record_visit(&self, document: &mut Document, changed: bool) {
+        let interval = if document.revisit_secs <= 0 {
+            self.initial_interval
+        } else {
+            let current = Duration::from_secs(document.revisit_secs as u64);
+            self.next_interval(current, changed)
+        };
+        if document.fetch_count > 0 && changed {
+            document.change_count += 1;
+        }
+        document.fetch_count += 1;
+        document.revisit_secs = interval.as_secs() as i64;
+        document.next_fetch_at = Some(DateTime::from_millis(
+            DateTime::now().timestamp_millis() + interval.as_millis() as i64,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn test_interval_adapts_and_is_bounded() {
+        let policy = RevisitPolicy::default();
+        let mut interval = policy.initial_interval;
+        for _ in 0..10 {
+            interval = policy.next_interval(interval, true);
+        }
+        assert_eq!(interval, HOUR);
+
+        for _ in 0..20 {
+            interval = policy.next_interval(interval, false);
+        }
+        assert_eq!(interval, policy.max_interval);
+    }
+
+    #[test]
+    fn test_record_visit() {
+        let policy = RevisitPolicy::default();
+        let mut document = Document::default();
+
+        policy.record_visit(&mut document, true);
+        assert_eq!(document.fetch_count, 1);
+        assert_eq!(document.change_count, 0);
+        assert_eq!(document.revisit_secs, 24 * 60 * 60);
+
+        policy.record_visit(&mut document, true);
+        assert_eq!(document.fetch_count, 2);
+        assert_eq!(document.change_count, 1);
+        assert_eq!(document.revisit_secs, 12 * 60 * 60);
+        assert!(document.next_fetch_at.unwrap() > DateTime::now());
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    mem,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+use common::{
+    merge::{compact, MergePolicy},
+    segment::{index_dir, SegmentError, SegmentWriter},
+};
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    models::{Document, Words},
+    utils::env_or,
+};
+
+// Collects the pages a crawl indexes and writes them to the index directory
+// as a new segment every `max_docs` pages, and once more when the crawl ends.
+// With a merge policy, each new segment starts a compaction in the
+// background unless one is already running.
+pub struct SegmentBuffer {
+    dir: PathBuf,
+    max_docs: usize,
+    pending: Mutex<SegmentWriter>,
+    policy: Option<MergePolicy>,
+    compacting: Arc<AtomicBool>,
+}
+
+impl SegmentBuffer {
+    pub fn new(dir: PathBuf, max_docs: usize) -> SegmentBuffer {
+        SegmentBuffer {
+            dir,
+            max_docs: max_docs.max(1),
+            pending: Mutex::new(SegmentWriter::new()),
+            policy: None,
+            compacting: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn from_env() -> SegmentBuffer {
+        SegmentBuffer {
+            policy: Some(merge_policy()),
+            ..SegmentBuffer::new(index_dir(), env_or("SEGMENT_MAX_DOCS", 1000))
+        }
+    }
+
+    fn pending(&self) -> MutexGuard<'_, SegmentWriter> {
+        self.pending.lock().unwrap()
+    }
+
+    // Returns the pages written to disk when this one filled the buffer
+    pub fn add(
+        &self,
+        document: &Document,
+        words: Vec<Words>,
+    ) -> Result<Vec<ObjectId>, SegmentError> {
+        let full = {
+            let mut pending = self.pending();
+            pending.add(document, words);
+            pending.len() >= self.max_docs
+        };
+        if full {
+            return self.flush();
+        }
+        Ok(Vec::new())
+    }
+
+    // Hides a page that left the index from the segments already written
+    pub fn remove(&self, id: ObjectId) {
+        self.pending().remove(id);
+    }
+
+    // Writes out whatever is pending and returns the pages written. After
+    // a failed write the pages stay pending for the next flush. The lock is
+    // held throughout so two flushes never pick the same file.
+    pub fn flush(&self) -> Result<Vec<ObjectId>, SegmentError> {
+        let mut pending = self.pending();
+        let writer = mem::take(&mut *pending);
+        if writer.is_empty() {
+            return Ok(Vec::new());
+        }
+        let path = match writer.write(&self.dir) {
+            Ok(path) => path,
+            Err(e) => {
+                *pending = writer;
+                return Err(e);
+            }
+        };
+        println!("Wrote {} pages to {}", writer.len(), path.display());
+        drop(pending);
+        self.compact();
+        Ok(writer.ids())
+    }
+
+    fn compact(&self) {
+        let Some(policy) = self.policy.clone() else {
+            return;
+        };
+        if self.compacting.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let dir = self.dir.clone();
+        let compacting = Arc::clone(&self.compacting);
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = compact(&dir, &policy) {
+                println!("Failed to compact {}: {}", dir.display(), e);
+            }
+            compacting.store(false, Ordering::Release);
+        });
+    }
+}
+
+fn merge_policy() -> MergePolicy {
+    let default = MergePolicy::default();
+    MergePolicy {
+        merge_factor: env_or("MERGE_FACTOR", default.merge_factor).max(2),
+        max_deleted_ratio: env_or("MERGE_MAX_DELETED_RATIO", default.max_deleted_ratio),
+    }
+}
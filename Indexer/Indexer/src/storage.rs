@@ -0,0 +1,212 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use mongodb::{
+    bson::oid::ObjectId,
+    error::{ErrorKind, RETRYABLE_WRITE_ERROR, TRANSIENT_TRANSACTION_ERROR},
+};
+
+use crate::{
+    db::Database,
+    embedded::EmbeddedStorage,
+    errors::StateEvents,
+    memory::MemoryStorage,
+    models::{DeadLetter, Document, FrontierEntry, FrontierState},
+};
+
+#[derive(Debug)]
+pub enum StorageError {
+    Mongo(mongodb::error::Error),
+    // Anything that went wrong in the embedded or in-memory backend
+    Embedded(String),
+}
+
+impl StorageError {
+    // Failures a retry may get past, such as a dropped connection or a
+    // transaction that lost a write conflict
+    pub fn is_transient(&self) -> bool {
+        match self {
+            StorageError::Mongo(e) => {
+                e.contains_label(TRANSIENT_TRANSACTION_ERROR)
+                    || e.contains_label(RETRYABLE_WRITE_ERROR)
+                    || matches!(
+                        *e.kind,
+                        ErrorKind::Io(_)
+                            | ErrorKind::ConnectionPoolCleared { .. }
+                            | ErrorKind::ServerSelection { .. }
+                    )
+            }
+            StorageError::Embedded(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Mongo(e) => write!(f, "{}", e),
+            StorageError::Embedded(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<mongodb::error::Error> for StorageError {
+    fn from(e: mongodb::error::Error) -> Self {
+        StorageError::Mongo(e)
+    }
+}
+
+macro_rules! embedded_error {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for StorageError {
+                fn from(e: $error) -> Self {
+                    StorageError::Embedded(e.to_string())
+                }
+            }
+        )*
+    };
+}
+
+embedded_error!(
+    redb::Error,
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError,
+    mongodb::bson::ser::Error,
+    mongodb::bson::de::Error,
+    common::segment::SegmentError
+);
+
+// Everything the indexer reads from or writes to its database
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // A page counts as indexed when its URL, under either scheme, was stored
+    // as the URL, redirect target or canonical URL of a document.
+    async fn url_exists(&self, url: &str) -> Result<bool, StorageError>;
+
+    // The stored document for a URL, matched the same way as `url_exists`
+    async fn find_document(&self, url: &str) -> Result<Option<Document>, StorageError>;
+
+    // URLs of every indexed document, for a re-crawl
+    async fn document_urls(&self) -> Result<Vec<String>, StorageError>;
+
+    // URLs whose next revisit is due, oldest first. Documents indexed
+    // before revisits were scheduled count as due.
+    async fn due_urls(&self, limit: i64) -> Result<Vec<String>, StorageError>;
+
+    // Moves the next revisit of `urls` to `delay` from now
+    async fn postpone_revisits(&self, urls: &[String], delay: Duration)
+        -> Result<(), StorageError>;
+
+    // Records a revisit that found nothing new: only the validators and
+    // the revisit bookkeeping of `document` are written
+    async fn touch_document(&self, document: &Document) -> Result<(), StorageError>;
+
+    // Stores a newly indexed page. Its words go to the index segments, so
+    // it is always stored `unindexed` until `mark_indexed` says they are on
+    // disk.
+    async fn try_commit(&self, document: Document) -> Result<(), StorageError>;
+
+    // Swaps a re-crawled document in for the stored one. The new document
+    // must carry the `_id` of the one it replaces, and is stored `unindexed`
+    // like in `try_commit`.
+    async fn replace_commit(&self, document: Document) -> Result<(), StorageError>;
+
+    // Clears `unindexed` on documents whose words were written to a segment
+    async fn mark_indexed(&self, ids: &[ObjectId]) -> Result<(), StorageError>;
+
+    // Up to `limit` documents stored without their words reaching a
+    // segment, e.g. because the process stopped before a flush
+    async fn unindexed_documents(&self, limit: i64) -> Result<Vec<Document>, StorageError>;
+
+    // Removes every document stored under `url`. Used when a page answers
+    // 410 Gone. Returns how many documents went.
+    async fn remove_document(&self, url: &str) -> Result<u64, StorageError>;
+
+    // Adds a URL to the persistent frontier unless it is already known.
+    // Returns true when the URL was not seen by any earlier run.
+    async fn frontier_enqueue(
+        &self,
+        url: &str,
+        depth: u32,
+        lastmod: Option<String>,
+    ) -> Result<bool, StorageError>;
+
+    async fn frontier_mark(
+        &self,
+        url: &str,
+        state: FrontierState,
+        reason: Option<StateEvents>,
+        error: Option<String>,
+    ) -> Result<(), StorageError>;
+
+    // Loads the frontier left behind by earlier runs. Anything that was in
+    // flight when the process died is put back in the queue first.
+    async fn frontier_resume(&self) -> Result<Vec<FrontierEntry>, StorageError>;
+
+    // Records a URL that failed for good, replacing any earlier entry for it
+    async fn dead_letter_add(&self, letter: DeadLetter) -> Result<(), StorageError>;
+
+    // Moves every dead letter back into the frontier as queued so the next
+    // crawl tries it again. Returns how many URLs were requeued.
+    async fn dead_letters_requeue(&self) -> Result<usize, StorageError>;
+
+    // Schema version recorded in the metadata, None before the first migration
+    async fn schema_version(&self) -> Result<Option<u32>, StorageError>;
+
+    async fn set_schema_version(&self, version: u32) -> Result<(), StorageError>;
+
+    // Deletes the per-word rows that schema versions before 3 wrote next to
+    // each document. Words live in the index segments since.
+    async fn drop_words(&self) -> Result<(), StorageError>;
+
+    // Up to `limit` documents written with a schema older than `version`
+    async fn outdated_documents(
+        &self,
+        version: u32,
+        limit: i64,
+    ) -> Result<Vec<Document>, StorageError>;
+}
+
+// Opens the backend named by STORAGE_BACKEND: `mongo` (the default),
+// `embedded`, a single file at STORAGE_PATH that needs no server, or
+// `memory`, which keeps nothing once the process exits
+pub async fn open_storage() -> Arc<dyn Storage> {
+    let backend = dotenv::var("STORAGE_BACKEND").unwrap_or_else(|_| "mongo".to_string());
+    match backend.trim() {
+        "embedded" => {
+            let path =
+                dotenv::var("STORAGE_PATH").unwrap_or_else(|_| "../search_engine.redb".to_string());
+            match EmbeddedStorage::open(&path) {
+                Ok(storage) => {
+                    println!("Opened embedded storage at {}", path);
+                    Arc::new(storage)
+                }
+                Err(e) => {
+                    panic!("{}", e);
+                }
+            }
+        }
+        "memory" => Arc::new(MemoryStorage::new()),
+        "mongo" => Arc::new(Database::new().await),
+        other => {
+            panic!("Unknown STORAGE_BACKEND: {}", other);
+        }
+    }
+}
+
+// The same URL under http and https
+pub fn scheme_variants(url: &str) -> Vec<String> {
+    let mut variants = vec![url.to_string()];
+    if let Some(rest) = url.strip_prefix("https://") {
+        variants.push(format!("http://{}", rest));
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        variants.push(format!("https://{}", rest));
+    }
+    variants
+}
@@ -1,20 +1,15 @@
-use human_regex::{
-    any, exactly, one_or_more, or, punctuation, text as htext, whitespace, word_boundary,
-    zero_or_more,
-};
+use human_regex::{any, text as htext, zero_or_more};
 use lol_html::{element, rewrite_str, text, RewriteStrSettings};
-use std::{
-    collections::HashMap,
-    fmt,
-    fs::File,
-    io::Write,
-    path::Path,
-};
-use stop_words::{get as sget, LANGUAGE};
+use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use common::text::tokenize;
 
-use crate::models::{Document, Words};
+use crate::{
+    errors::IndexerError,
+    models::{Document, Field, Positions, Words},
+};
 
-pub fn is_binary_extension(url: &String) -> bool {
+pub fn is_binary_extension(url: &str) -> bool {
     let binary_extensions = [
         ".exe", ".apk", ".dmg", ".pkg", ".deb", ".rpm", ".zip", ".rar", ".7z", ".tar", ".gz",
         ".bz2", ".pdf", ".doc", ".docx", ".xls", ".xlsx", ".ppt", ".pptx", ".jpg", ".jpeg", ".png",
@@ -27,7 +22,7 @@ pub fn is_binary_extension(url: &String) -> bool {
         .iter()
         .any(|&ext| url_lower.ends_with(ext))
 }
-pub fn is_text_content(content_type: &String) -> bool {
+pub fn is_text_content(content_type: &str) -> bool {
     let allowed_types = [
         "text/html",
         "text/plain",
@@ -45,92 +40,16 @@ pub fn is_text_content(content_type: &String) -> bool {
         .any(|&allowed| content_type.starts_with(allowed))
 }
 
-pub fn save_idf(num_doc: usize, global_count: &HashMap<String, i32>) {
-    let mut idf: HashMap<String, f32> = HashMap::new();
-    let doc_num = num_doc as f32 + 1.0;
-    let mn = f32::MAX;
-    let mx = f32::MIN;
-    for (word, count) in global_count.iter() {
-        idf.entry(word.clone())
-            .or_insert(f32::log10(doc_num / (*count as f32 + 1.0)) + 1.0);
-    }
-
-    let jsn = serde_json::to_string(&idf).unwrap();
-
-    let path = Path::new("../out.json");
-    let mut file = match File::create(&path) {
-        Ok(file) => file,
-        Err(e) => {
-            panic!("File creation failed:{}", e);
-        }
-    };
-
-    match file.write(&jsn.as_bytes()) {
-        Ok(_) => {
-            print!("File creation success")
-        }
-        Err(_) => {
-            panic!("file failed")
-        }
-    };
-}
-
-pub struct Store {
-    url: String,
-    pub tf_score: HashMap<String, i32>,
-}
-
-impl Store {
-    pub fn new(url: String, tf_score: HashMap<String, i32>) -> Store {
-        Store { url, tf_score }
-    }
-}
-
-fn clean_corpus(document: String) -> String {
-    let words = sget(LANGUAGE::English);
-    //println!("{:?}", words);
-    // Remove punctuation and lowercase the text to make parsing easier
-    let lowercase_doc = document.to_ascii_lowercase();
-    let regex_for_punctuation = one_or_more(punctuation());
-
-    //println!("{}", regex_for_punctuation.to_regex());
-
-    let text_without_punctuation = regex_for_punctuation
-        .to_regex()
-        .replace_all(&lowercase_doc, " ");
-    // Make a regex to match stopwords with trailing spaces and punctuation
-    let regex_for_stop_words =
-        word_boundary() + exactly(1, or(&words)) + word_boundary() + one_or_more(whitespace());
-    //println!("{}", regex_for_stop_words.to_regex());
-    // Remove stop words
-    let clean_text = regex_for_stop_words
-        .to_regex()
-        .replace_all(&text_without_punctuation, "");
-    clean_text.to_string()
-}
-
-#[derive(Debug)]
-pub struct ExtractedData {
-    title: String,
-    description: String,
-    canonical_url: String,
-    summary_text: String,
-    full_text: Vec<String>,
-}
-impl fmt::Display for ExtractedData {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "ExtractedData {{")?;
-        writeln!(f, "  title: {:?}", self.title)?;
-        writeln!(f, "  description: {:?}", self.description)?;
-        writeln!(f, "  canonical_url: {:?}", self.canonical_url)?;
-        writeln!(f, "  summary_text: \"{}\"", self.summary_text)?;
-        writeln!(f, "  full_text: {:?} words", self.full_text.len())?;
-        write!(f, "}}")
+// Reads a setting from the environment or `.env`, falling back to `default`
+// when it is missing or does not parse
+pub fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match dotenv::var(key) {
+        Ok(val) => val.trim().parse().unwrap_or(default),
+        Err(_) => default,
     }
 }
 
-
-pub fn extract_structured_data(text: String,url: String) -> Document {
+pub fn extract_structured_data(text: String, url: String) -> Result<Document, IndexerError> {
     let mut og_title = None;
     let mut og_description = None;
     let mut og_url = None;
@@ -139,6 +58,7 @@ pub fn extract_structured_data(text: String,url: String) -> Document {
     let mut paragraphs = Vec::new();
     let mut page_title = None;
     let mut tmp = None;
+    let mut link_canonical = None;
     // Regex to remove brackets content (similar to BRACKETS_PATTERN)
 
     let brackets_pattern = (htext("[") + zero_or_more(any()).lazy() + htext("]")).to_regex();
@@ -164,6 +84,22 @@ pub fn extract_structured_data(text: String,url: String) -> Document {
                     }
                     Ok(())
                 }),
+                // Extract <link rel="canonical">
+                element!("link[rel][href]", |el| {
+                    let rel = el.get_attribute("rel").unwrap_or_default();
+                    if rel
+                        .split_whitespace()
+                        .any(|r| r.eq_ignore_ascii_case("canonical"))
+                    {
+                        if let Some(href) = el.get_attribute("href") {
+                            let href = href.trim().to_string();
+                            if !href.is_empty() && link_canonical.is_none() {
+                                link_canonical = Some(href);
+                            }
+                        }
+                    }
+                    Ok(())
+                }),
                 // Extract meta tags with property attributes (Open Graph)
                 element!("meta[property]", |el| {
                     if let (Some(property), Some(content)) =
@@ -202,7 +138,7 @@ pub fn extract_structured_data(text: String,url: String) -> Document {
             ..RewriteStrSettings::new()
         },
     )
-    .unwrap();
+    .map_err(|e| IndexerError::Parse(e.to_string()))?;
 
     let title = og_title
         .or(meta_title)
@@ -211,7 +147,7 @@ pub fn extract_structured_data(text: String,url: String) -> Document {
     let description = og_description
         .or(meta_description)
         .unwrap_or("".to_string());
-    let canonical_url = og_url.or(tmp).unwrap_or("".to_string());
+    let canonical_url = link_canonical.or(og_url).or(tmp).unwrap_or("".to_string());
 
     let page_text = brackets_pattern
         .replace_all(paragraphs.join(" ").as_str(), " ")
@@ -225,55 +161,115 @@ pub fn extract_structured_data(text: String,url: String) -> Document {
         words[..500].join(" ")
     };
 
-    let text: Vec<String> = clean_corpus(page_text)
-        .split(" ")
-        .map(|x| x.trim().to_string())
-        .filter(|x| !x.is_empty())
-        .collect();
+    let text = tokenize(page_text);
 
-    Document::new(url,title, description, canonical_url, summary_text, text)
+    Ok(Document::new(url,title, description, canonical_url, summary_text, text))
 }
 
-pub fn create_frequency(data: &Document) -> Vec<Words> {
-    let mut count: HashMap<&String, i32> = HashMap::new();
-    let corpus = data.get_full_text();
-    for i in corpus {
-        count.entry(i).and_modify(|x| *x += 1).or_insert(1);
+// Fingerprint of what gets indexed from a page, so markup-only changes
+// such as a rotated ad or a new timestamp do not count as a change
+pub fn content_hash(data: &Document) -> String {
+    let mut hasher = Sha256::new();
+    for part in [&data.title, &data.description, &data.canonical_url] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
     }
-    //adding multiplier to keyword appearing in title
-    //println!("{}", data.get_title());
-    let title: Vec<String> = clean_corpus(data.get_title())
-        .split(" ")
-        .map(|x| x.trim().to_string())
-        .filter(|x| !x.is_empty())
-        .collect();
-    //println!("{:?}", title);
-    for i in &title {
-        count.entry(i).and_modify(|x| *x += 50);
+    for word in &data.full_text {
+        hasher.update(word.as_bytes());
+        hasher.update(b" ");
     }
-    //adding multiplier to keyword appearing in descripton
-    //println!("{}", data.get_description());
-    let descripton: Vec<String> = clean_corpus(data.get_description())
-        .split(" ")
-        .map(|x| x.trim().to_string())
-        .filter(|x| !x.is_empty())
-        .collect();
-    //println!("{:?}", descripton);
-    for i in &descripton {
-        count.entry(i).and_modify(|x| *x += 10);
+    format!("{:x}", hasher.finalize())
+}
+
+// Terms kept per page, the most heavily weighted first
+const MAX_TERMS: usize = 1000;
+
+// One record per distinct word of the page, holding its weighted count and
+// its positions in each field. A word counts 1 for each time it appears in
+// the body, 50 in the title and 10 in the description. Words that appear
+// only in the title or description are recorded as well, with just those
+// weights, so that `title:` and `description:` queries and phrases in those
+// fields can find them. Only the `MAX_TERMS` heaviest words are kept.
+pub fn create_frequency(data: &Document) -> Vec<Words> {
+    let mut postings: HashMap<&String, (i32, Positions)> = HashMap::new();
+    let title = tokenize(data.get_title());
+    let description = tokenize(data.get_description());
+    //adding multiplier to keyword appearing in title or description
+    for (field, tokens, weight) in [
+        (Field::Body, data.get_full_text(), 1),
+        (Field::Title, &title, 50),
+        (Field::Description, &description, 10),
+    ] {
+        for (position, word) in tokens.iter().enumerate() {
+            let (count, positions) = postings.entry(word).or_default();
+            *count += weight;
+            positions.push(field, position as u32);
+        }
     }
 
-    let mut key_val_pairs: Vec<(&String, i32)> = count.into_iter().collect();
+    let mut key_val_pairs: Vec<(&String, (i32, Positions))> = postings.into_iter().collect();
+    key_val_pairs.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then_with(|| a.0.cmp(b.0)));
+    let top_words = key_val_pairs.into_iter().take(MAX_TERMS);
+
+    let mut words_arr: Vec<Words> = Vec::new();
+    for (word, (count, positions)) in top_words {
+        let mut words = Words::new(data.get_id(), word.clone(), count);
+        words.positions = positions;
+        words_arr.push(words);
+    }
+    words_arr
+}
 
-    key_val_pairs.sort_by(|a, b| b.1.cmp(&a.1));
-    let top_words = key_val_pairs.into_iter().take(1000);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    //println!("{:?}", top_words);
-    let mut words_arr:Vec<Words>=Vec::new();
-    for (i,j) in top_words{
-        words_arr.push(
-            Words::new(data.get_id(), i.clone(),j)
+    #[test]
+    fn test_create_frequency_records_positions() {
+        let document = Document::new(
+            "https://example.com/".to_string(),
+            "Rust search engine".to_string(),
+            "A small engine".to_string(),
+            String::new(),
+            String::new(),
+            ["engine", "written", "rust", "engine"]
+                .map(String::from)
+                .to_vec(),
         );
+        let words = create_frequency(&document);
+        let find = |word: &str| words.iter().find(|w| w.word == word).unwrap();
+
+        let engine = find("engine");
+        assert_eq!(engine.positions.body, [0, 3]);
+        assert_eq!(engine.positions.title, [2]);
+        assert_eq!(engine.positions.description, [1]);
+        assert_eq!(engine.count, 2 + 50 + 10);
+        // Words only in the title are indexed too
+        let search = find("search");
+        assert_eq!(search.positions.get(Field::Title), [1]);
+        assert!(search.positions.body.is_empty());
+        assert_eq!(search.count, 50);
+        assert_eq!(words[0].word, "engine");
+    }
+
+    #[test]
+    fn test_create_frequency_keeps_heaviest_terms() {
+        let mut body: Vec<String> = (0..MAX_TERMS + 10).map(|i| format!("w{}", i)).collect();
+        body.push("w0".to_string());
+        let document = Document::new(
+            "https://example.com/".to_string(),
+            "Crabs".to_string(),
+            String::new(),
+            String::new(),
+            String::new(),
+            body,
+        );
+        let words = create_frequency(&document);
+
+        assert_eq!(words.len(), MAX_TERMS);
+        // The title-only word outweighs every body word, then the repeat
+        assert_eq!((words[0].word.as_str(), words[0].count), ("crabs", 50));
+        assert_eq!((words[1].word.as_str(), words[1].count), ("w0", 2));
+        assert!(words[2..].iter().all(|w| w.count == 1));
     }
-    words_arr
 }
@@ -1,11 +1,14 @@
 use std::collections::HashMap;
-use mongodb::bson::oid::ObjectId;
 
 mod db;
-mod models;
+mod memory;
+mod storage;
 
-use db::Database;
-use models::{Document, Words, TfIdfScore};
+use storage::{open_storage, Storage};
+use common::{
+    models::TfIdfScore,
+    segment::{index_dir, Index},
+};
 
 #[tokio::main]
 async fn main() {
@@ -13,71 +16,67 @@ async fn main() {
     
     println!("Starting TF-IDF computation...");
     
-    let database = Database::new().await;
+    let database = open_storage().await;
+    let index = match Index::open(&index_dir()) {
+        Ok(index) => index,
+        Err(e) => {
+            println!("Error opening the index at {}: {}", index_dir().display(), e);
+            return;
+        }
+    };
     
-    match compute_tf_idf(&database).await {
+    match compute_tf_idf(database.as_ref(), &index).await {
         Ok(_) => println!("TF-IDF computation completed successfully!"),
         Err(e) => println!("Error during TF-IDF computation: {}", e),
     }
 }
 
-async fn compute_tf_idf(db: &Database) -> Result<(), Box<dyn std::error::Error>> {
+// Scores every live document of `index` and replaces the stored scores
+async fn compute_tf_idf(db: &dyn Storage, index: &Index) -> Result<(), Box<dyn std::error::Error>> {
     // Clear existing TF-IDF scores
     db.delete_tf_idf_scores().await?;
     
-    // Get all documents and words from the database
-    let documents = db.get_documents_metadata().await?;
-    let words = db.get_all_words().await?;
-    
-    if documents.is_empty() {
-        println!("No documents found in the database.");
-        return Ok(());
-    }
-    
-    if words.is_empty() {
-        println!("No words found in the database.");
+    if index.doc_count() == 0 {
+        println!("No documents found in the index.");
         return Ok(());
     }
     
-    println!("Processing {} documents and {} words", documents.len(), words.len());
-    
     // Build document frequency map (how many documents contain each word)
     let mut document_frequency: HashMap<String, usize> = HashMap::new();
-    let mut document_word_counts: HashMap<ObjectId, HashMap<String, i32>> = HashMap::new();
-    let mut document_urls: HashMap<ObjectId, String> = HashMap::new();
-    
-    // Store document URLs for later reference
-    for doc in &documents {
-        document_urls.insert(doc._id, doc.url.clone());
+    // Words of each document, keyed by its segment and position in it
+    let mut document_word_counts: HashMap<(usize, u32), HashMap<String, u32>> = HashMap::new();
+    
+    // Walk each segment's term dictionary, skipping documents a newer
+    // segment replaced or removed
+    for (i, segment) in index.segments().iter().enumerate() {
+        for term in segment.terms()? {
+            for posting in segment.postings(&term)? {
+                if index.is_deleted(i, posting.doc) {
+                    continue;
+                }
+                // Count how many times each word appears in each document
+                document_word_counts
+                    .entry((i, posting.doc))
+                    .or_default()
+                    .insert(term.clone(), posting.count);
+                
+                // Count in how many documents each word appears
+                *document_frequency.entry(term.clone()).or_insert(0) += 1;
+            }
+        }
     }
     
-    // Group words by document and count document frequency
-    for word in &words {
-        // Count how many times each word appears in each document
-        document_word_counts
-            .entry(word.document)
-            .or_insert_with(HashMap::new)
-            .insert(word.word.clone(), word.count);
-        
-        // Count in how many documents each word appears
-        *document_frequency.entry(word.word.clone()).or_insert(0) += 1;
-    }
+    println!("Processing {} documents and {} words", index.doc_count(), document_frequency.len());
     
-    let total_documents = documents.len() as f64;
+    let total_documents = index.doc_count() as f64;
     let mut tf_idf_scores = Vec::new();
     
     // Compute TF-IDF for each word in each document
-    for (doc_id, word_counts) in document_word_counts {
-        let url = match document_urls.get(&doc_id) {
-            Some(url) => url.clone(),
-            None => {
-                println!("Warning: Document ID {} not found, skipping words for this document", doc_id);
-                continue;
-            }
-        };
+    for ((i, doc), word_counts) in document_word_counts {
+        let document = index.segments()[i].document(doc)?;
         
         // Calculate total words in this document
-        let total_words_in_doc: i32 = word_counts.values().sum();
+        let total_words_in_doc: u32 = word_counts.values().sum();
         
         for (word, count) in word_counts {
             // Calculate Term Frequency (TF)
@@ -88,7 +87,7 @@ async fn compute_tf_idf(db: &Database) -> Result<(), Box<dyn std::error::Error>>
             let idf = (total_documents / df).ln() + 1.0;
             
             // Create TF-IDF score entry
-            let tf_idf_score = TfIdfScore::new(word, doc_id, url.clone(), tf, idf);
+            let tf_idf_score = TfIdfScore::new(word, document.id, document.url.clone(), tf, idf);
             tf_idf_scores.push(tf_idf_score);
         }
     }
@@ -108,3 +107,71 @@ async fn compute_tf_idf(db: &Database) -> Result<(), Box<dyn std::error::Error>>
     println!("TF-IDF computation and storage completed!");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStorage;
+    use common::{
+        models::{Document, Words},
+        segment::SegmentWriter,
+    };
+    use mongodb::bson::oid::ObjectId;
+
+    fn indexed(writer: &mut SegmentWriter, url: &str, counts: &[(&str, i32)]) -> ObjectId {
+        let document = Document {
+            url: url.to_string(),
+            ..Document::default()
+        };
+        let id = document.get_id();
+        let words = counts
+            .iter()
+            .map(|(word, count)| {
+                let mut words = Words::new(id, word.to_string(), *count);
+                words.positions.body = (0..*count as u32).collect();
+                words
+            })
+            .collect();
+        writer.add(&document, words);
+        id
+    }
+
+    #[tokio::test]
+    async fn test_compute_tf_idf() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = SegmentWriter::new();
+        let first = indexed(&mut writer, "https://a.com/", &[("rust", 3), ("crab", 1)]);
+        indexed(&mut writer, "https://b.com/", &[("rust", 2)]);
+        let removed = indexed(&mut writer, "https://c.com/", &[("crab", 5)]);
+        writer.write(dir.path()).unwrap();
+        // Documents a newer segment removed are not scored
+        let mut writer = SegmentWriter::new();
+        writer.remove(removed);
+        writer.write(dir.path()).unwrap();
+        let index = Index::open(dir.path()).unwrap();
+
+        let storage = MemoryStorage::new();
+        compute_tf_idf(&storage, &index).await.unwrap();
+        let scores = storage.tf_idf_scores();
+        assert_eq!(scores.len(), 3);
+        let score = |word: &str, document: ObjectId| {
+            scores
+                .iter()
+                .find(|s| s.word == word && s.document_id == document)
+                .unwrap()
+                .clone()
+        };
+
+        let rust = score("rust", first);
+        assert_eq!(rust.tf, 0.75);
+        assert_eq!(rust.idf, 1.0);
+        let crab = score("crab", first);
+        assert_eq!(crab.url, "https://a.com/");
+        assert_eq!(crab.idf, 2f64.ln() + 1.0);
+        assert!(crab.tf_idf > 0.25);
+
+        // A second run replaces the scores instead of adding to them
+        compute_tf_idf(&storage, &index).await.unwrap();
+        assert_eq!(storage.tf_idf_scores().len(), 3);
+    }
+}
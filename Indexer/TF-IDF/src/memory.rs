@@ -0,0 +1,46 @@
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+
+use common::models::TfIdfScore;
+
+use crate::storage::{Storage, StorageError};
+
+#[derive(Default)]
+struct Tables {
+    tf_idf_scores: Vec<TfIdfScore>,
+}
+
+// Storage that lives only as long as the process, for tests and dry runs
+#[derive(Default)]
+pub struct MemoryStorage {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap()
+    }
+
+    #[cfg(test)]
+    pub fn tf_idf_scores(&self) -> Vec<TfIdfScore> {
+        self.tables().tf_idf_scores.clone()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn delete_tf_idf_scores(&self) -> Result<(), StorageError> {
+        self.tables().tf_idf_scores.clear();
+        Ok(())
+    }
+
+    async fn insert_tf_idf_scores(&self, scores: Vec<TfIdfScore>) -> Result<(), StorageError> {
+        self.tables().tf_idf_scores.extend(scores);
+        Ok(())
+    }
+}
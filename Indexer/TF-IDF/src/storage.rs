@@ -0,0 +1,174 @@
+use std::{fmt, path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use common::{
+    models::TfIdfScore,
+    tables::{score_key, TF_IDF_SCORES},
+};
+use mongodb::bson;
+
+use crate::{db::Database, memory::MemoryStorage};
+
+#[derive(Debug)]
+pub enum StorageError {
+    Mongo(mongodb::error::Error),
+    // Anything that went wrong in the embedded or in-memory backend
+    Embedded(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Mongo(e) => write!(f, "{}", e),
+            StorageError::Embedded(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<mongodb::error::Error> for StorageError {
+    fn from(e: mongodb::error::Error) -> Self {
+        StorageError::Mongo(e)
+    }
+}
+
+macro_rules! embedded_error {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for StorageError {
+                fn from(e: $error) -> Self {
+                    StorageError::Embedded(e.to_string())
+                }
+            }
+        )*
+    };
+}
+
+embedded_error!(
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError,
+    bson::ser::Error,
+    bson::de::Error
+);
+
+// Everything the TF-IDF job reads from or writes to the database
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn delete_tf_idf_scores(&self) -> Result<(), StorageError>;
+
+    async fn insert_tf_idf_scores(&self, scores: Vec<TfIdfScore>) -> Result<(), StorageError>;
+}
+
+// Opens the backend named by STORAGE_BACKEND, the same way the indexer does
+pub async fn open_storage() -> Arc<dyn Storage> {
+    let backend = dotenv::var("STORAGE_BACKEND").unwrap_or_else(|_| "mongo".to_string());
+    match backend.trim() {
+        "embedded" => {
+            let path =
+                dotenv::var("STORAGE_PATH").unwrap_or_else(|_| "../search_engine.redb".to_string());
+            match EmbeddedStorage::open(&path) {
+                Ok(storage) => {
+                    println!("Opened embedded storage at {}", path);
+                    Arc::new(storage)
+                }
+                Err(e) => {
+                    panic!("{}", e);
+                }
+            }
+        }
+        "memory" => Arc::new(MemoryStorage::new()),
+        "mongo" => Arc::new(Database::new().await),
+        other => {
+            panic!("Unknown STORAGE_BACKEND: {}", other);
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for Database {
+    async fn delete_tf_idf_scores(&self) -> Result<(), StorageError> {
+        Ok(Database::delete_tf_idf_scores(self).await?)
+    }
+
+    async fn insert_tf_idf_scores(&self, scores: Vec<TfIdfScore>) -> Result<(), StorageError> {
+        Database::insert_tf_idf_scores(self, scores).await?;
+        Ok(())
+    }
+}
+
+// The redb file written by the indexer's embedded backend
+pub struct EmbeddedStorage {
+    db: redb::Database,
+}
+
+impl EmbeddedStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<EmbeddedStorage, StorageError> {
+        let db = redb::Database::create(path)?;
+        let txn = db.begin_write()?;
+        txn.open_table(TF_IDF_SCORES)?;
+        txn.commit()?;
+        Ok(EmbeddedStorage { db })
+    }
+}
+
+#[async_trait]
+impl Storage for EmbeddedStorage {
+    async fn delete_tf_idf_scores(&self) -> Result<(), StorageError> {
+        let txn = self.db.begin_write()?;
+        txn.delete_table(TF_IDF_SCORES)?;
+        txn.open_table(TF_IDF_SCORES)?;
+        txn.commit()?;
+        println!("All TF-IDF scores deleted successfully");
+        Ok(())
+    }
+
+    async fn insert_tf_idf_scores(&self, scores: Vec<TfIdfScore>) -> Result<(), StorageError> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(TF_IDF_SCORES)?;
+            for score in &scores {
+                let key = score_key(score);
+                table.insert(key.as_str(), bson::to_vec(score)?.as_slice())?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use redb::ReadableTableMetadata;
+
+    use super::*;
+    use common::models::Document;
+
+    #[tokio::test]
+    async fn test_embedded_replaces_scores() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = EmbeddedStorage::open(dir.path().join("test.redb")).unwrap();
+        let document = Document {
+            url: "https://example.com/".to_string(),
+            ..Document::default()
+        };
+        let score = TfIdfScore::new(
+            "example".to_string(),
+            document.get_id(),
+            document.url.clone(),
+            1.0,
+            1.0,
+        );
+        storage.insert_tf_idf_scores(vec![score]).await.unwrap();
+        {
+            let txn = storage.db.begin_read().unwrap();
+            assert_eq!(txn.open_table(TF_IDF_SCORES).unwrap().len().unwrap(), 1);
+        }
+        storage.delete_tf_idf_scores().await.unwrap();
+        let txn = storage.db.begin_read().unwrap();
+        assert_eq!(txn.open_table(TF_IDF_SCORES).unwrap().len().unwrap(), 0);
+    }
+}
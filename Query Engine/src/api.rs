@@ -0,0 +1,296 @@
+// JSON API over the query engine, for the front-end
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use common::models::Document;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    index::IndexReader,
+    query::parse,
+    search::{search, SearchResult},
+    storage::{Storage, StorageError},
+};
+
+pub const DEFAULT_PAGE_SIZE: usize = 10;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+type Db = Arc<dyn Storage>;
+
+#[derive(Clone)]
+struct AppState {
+    db: Db,
+    index: Arc<IndexReader>,
+}
+
+pub fn router(db: Db, index: Arc<IndexReader>) -> Router {
+    Router::new()
+        .route("/search", get(search_documents))
+        .route("/doc/{id}", get(get_document))
+        .with_state(AppState { db, index })
+}
+
+pub enum ApiError {
+    BadRequest(String),
+    NotFound,
+    Storage(StorageError),
+}
+
+impl From<StorageError> for ApiError {
+    fn from(e: StorageError) -> Self {
+        ApiError::Storage(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::NotFound => (StatusCode::NOT_FOUND, "document not found".to_string()),
+            ApiError::Storage(e) => {
+                println!("Storage error while serving a request: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "storage error".to_string(),
+                )
+            }
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+// `page` counts from 1
+#[derive(Deserialize)]
+pub struct SearchParams {
+    #[serde(default)]
+    q: String,
+    page: Option<usize>,
+    size: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct SearchHit {
+    id: String,
+    url: String,
+    title: String,
+    description: String,
+    snippet: String,
+    score: f64,
+}
+
+impl From<SearchResult> for SearchHit {
+    fn from(result: SearchResult) -> Self {
+        SearchHit {
+            id: result.document._id.to_hex(),
+            url: result.document.url,
+            title: result.document.title,
+            description: result.document.description,
+            snippet: result.snippet,
+            score: result.score,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SearchResponse {
+    query: String,
+    page: usize,
+    size: usize,
+    total: usize,
+    results: Vec<SearchHit>,
+}
+
+// GET /search?q=&page=&size=, with `q` in the syntax of `query`
+async fn search_documents(
+    State(state): State<AppState>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<SearchResponse>, ApiError> {
+    let query = params.q.trim();
+    if query.is_empty() {
+        return Err(ApiError::BadRequest("missing query `q`".to_string()));
+    }
+    let page = params.page.unwrap_or(1).max(1);
+    let size = params
+        .size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = (page - 1)
+        .checked_mul(size)
+        .ok_or_else(|| ApiError::BadRequest("`page` is too large".to_string()))?;
+    let parsed = parse(query).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let index = state.index.index().map_err(StorageError::from)?;
+    let found = search(state.db.as_ref(), &index, &parsed, offset, size).await?;
+    Ok(Json(SearchResponse {
+        query: query.to_string(),
+        page,
+        size,
+        total: found.total,
+        results: found.results.into_iter().map(SearchHit::from).collect(),
+    }))
+}
+
+// A stored document with its id and dates as plain strings
+#[derive(Serialize)]
+pub struct DocumentResponse {
+    id: String,
+    url: String,
+    final_url: String,
+    canonical_url: String,
+    title: String,
+    description: String,
+    summary_text: String,
+    full_text: Vec<String>,
+    http_status: u16,
+    fetched_at: Option<String>,
+}
+
+impl From<Document> for DocumentResponse {
+    fn from(document: Document) -> Self {
+        DocumentResponse {
+            id: document.get_id().to_hex(),
+            fetched_at: document
+                .fetched_at
+                .and_then(|at| at.try_to_rfc3339_string().ok()),
+            url: document.url,
+            final_url: document.final_url,
+            canonical_url: document.canonical_url,
+            title: document.title,
+            description: document.description,
+            summary_text: document.summary_text,
+            full_text: document.full_text,
+            http_status: document.http_status,
+        }
+    }
+}
+
+// GET /doc/{id}
+async fn get_document(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<DocumentResponse>, ApiError> {
+    let id = ObjectId::parse_str(&id)
+        .map_err(|_| ApiError::BadRequest(format!("invalid document id `{}`", id)))?;
+    match state.db.find_document(id).await? {
+        Some(document) => Ok(Json(DocumentResponse::from(document))),
+        None => Err(ApiError::NotFound),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::models::TfIdfScore;
+    use serde_json::Value;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::memory::MemoryStorage;
+
+    // Serves the API on a local port and returns its base URL. Nothing
+    // here queries phrases or fields, so the index stays empty.
+    async fn serve(db: MemoryStorage) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let index = IndexReader::new(dir.path().to_path_buf());
+        tokio::spawn(async move {
+            let _dir = dir;
+            axum::serve(listener, router(Arc::new(db), Arc::new(index)))
+                .await
+                .unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn get(url: String) -> (StatusCode, Value) {
+        let response = reqwest::get(url).await.unwrap();
+        let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+        (status, response.json().await.unwrap())
+    }
+
+    fn crab_pages() -> (MemoryStorage, Vec<Document>) {
+        let db = MemoryStorage::new();
+        let mut documents = Vec::new();
+        for (i, tf_idf) in [0.3, 0.2, 0.1].into_iter().enumerate() {
+            let document = Document {
+                url: format!("https://example.com/{}", i),
+                summary_text: format!("All about crabs, part {}", i),
+                ..Document::default()
+            };
+            let score = TfIdfScore::new(
+                "crab".to_string(),
+                document.get_id(),
+                document.url.clone(),
+                tf_idf,
+                1.0,
+            );
+            db.add_document(document.clone(), vec![score]);
+            documents.push(document);
+        }
+        (db, documents)
+    }
+
+    #[tokio::test]
+    async fn test_search_endpoint_pages_results() {
+        let (db, documents) = crab_pages();
+        let base = serve(db).await;
+
+        let (status, body) = get(format!("{}/search?q=crab&page=2&size=2", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["page"], 2);
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["id"], documents[2].get_id().to_hex());
+        assert_eq!(results[0]["url"], "https://example.com/2");
+        assert_eq!(results[0]["snippet"], "All about crabs, part 2");
+        assert!((results[0]["score"].as_f64().unwrap() - 0.1).abs() < 1e-9);
+
+        let (status, body) = get(format!("{}/search?q=", base)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_search_endpoint_rejects_huge_pages() {
+        let (db, _) = crab_pages();
+        let base = serve(db).await;
+
+        let url = format!("{}/search?q=crab&page={}&size=10", base, usize::MAX);
+        let (status, body) = get(url).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].is_string());
+
+        // Far past the last result but still addressable
+        let (status, body) = get(format!("{}/search?q=crab&page=1000000", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["results"].as_array().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_document_endpoint() {
+        let (db, documents) = crab_pages();
+        let base = serve(db).await;
+
+        let id = documents[0].get_id().to_hex();
+        let (status, body) = get(format!("{}/doc/{}", base, id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], id);
+        assert_eq!(body["title"], documents[0].title);
+        assert_eq!(body["full_text"][0], documents[0].full_text[0]);
+
+        let missing = ObjectId::new().to_hex();
+        let (status, _) = get(format!("{}/doc/{}", base, missing)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = get(format!("{}/doc/not-an-id", base)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
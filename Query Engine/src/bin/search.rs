@@ -0,0 +1,204 @@
+// Runs queries from the command line, for debugging relevance. Given a
+// query it prints one page of results; without one it reads queries from
+// stdin until end of input, keeping every term it has loaded in memory.
+//
+//     search [--limit N] [query...]
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, Write},
+};
+
+use query_engine::{
+    cache::CachedStorage,
+    index::IndexReader,
+    query::parse,
+    search::{search, SearchResults},
+    storage::{open_storage, Storage, StorageError},
+};
+
+const DEFAULT_LIMIT: usize = 10;
+
+// How common a query term is across the index
+struct TermStats {
+    term: String,
+    documents: usize,
+    idf: f64,
+}
+
+async fn term_stats(db: &dyn Storage, terms: Vec<String>) -> Result<Vec<TermStats>, StorageError> {
+    let mut stats = Vec::new();
+    for term in terms {
+        let scores = db.scores_for(&term).await?;
+        stats.push(TermStats {
+            idf: scores.first().map_or(0.0, |score| score.idf),
+            documents: scores.len(),
+            term,
+        });
+    }
+    Ok(stats)
+}
+
+fn format_results(query: &str, stats: &[TermStats], found: &SearchResults) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:?}: {} of {} matching documents",
+        query,
+        found.results.len(),
+        found.total
+    );
+    for stat in stats {
+        let _ = writeln!(
+            out,
+            "  {:<20} idf {:.4} in {} documents",
+            stat.term, stat.idf, stat.documents
+        );
+    }
+    for (rank, result) in found.results.iter().enumerate() {
+        let contributions: Vec<String> = result
+            .contributions
+            .iter()
+            .map(|(term, score)| format!("{} {:.4}", term, score))
+            .collect();
+        let _ = writeln!(
+            out,
+            "{:>3}. {:.4}  {}",
+            rank + 1,
+            result.score,
+            result.document.title
+        );
+        let _ = writeln!(out, "     {}", result.document.url);
+        let _ = writeln!(out, "     {}", contributions.join(" + "));
+        if !result.snippet.is_empty() {
+            let _ = writeln!(out, "     {}", result.snippet);
+        }
+    }
+    out
+}
+
+async fn run_query(
+    db: &dyn Storage,
+    index: &IndexReader,
+    query: &str,
+    limit: usize,
+) -> Result<(), String> {
+    let parsed = parse(query).map_err(|e| format!("Invalid query: {}", e))?;
+    let failed = |e: StorageError| format!("Search failed: {}", e);
+    let stats = term_stats(db, parsed.terms()).await.map_err(failed)?;
+    let index = index.index().map_err(|e| failed(e.into()))?;
+    let found = search(db, &index, &parsed, 0, limit)
+        .await
+        .map_err(failed)?;
+    print!("{}", format_results(query, &stats, &found));
+    Ok(())
+}
+
+async fn repl(db: &CachedStorage, index: &IndexReader, limit: usize) {
+    println!("Enter a query per line, or `exit` to quit");
+    let stdin = io::stdin();
+    loop {
+        print!("search> ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                println!("Failed to read query: {}", e);
+                break;
+            }
+        }
+        let query = line.trim();
+        match query {
+            "" => continue,
+            "exit" | "quit" => break,
+            _ => {}
+        }
+        if let Err(e) = run_query(db, index, query, limit).await {
+            println!("{}", e);
+        }
+        println!("({} terms loaded)", db.len());
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), ()> {
+    dotenv::dotenv().ok();
+    let mut limit = DEFAULT_LIMIT;
+    let mut words = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" | "--limit" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => limit = n,
+                None => {
+                    println!("{} needs a number", arg);
+                    return Err(());
+                }
+            },
+            _ => words.push(arg),
+        }
+    }
+
+    let db = CachedStorage::new(open_storage().await);
+    let index = IndexReader::from_env();
+    if words.is_empty() {
+        repl(&db, &index, limit).await;
+        return Ok(());
+    }
+    run_query(&db, &index, &words.join(" "), limit)
+        .await
+        .map_err(|e| {
+            println!("{}", e);
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{
+        models::{Document, TfIdfScore},
+        segment::Index,
+    };
+    use query_engine::memory::MemoryStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_format_results() {
+        let db = MemoryStorage::new();
+        let document = Document {
+            title: "Crabs".to_string(),
+            url: "https://example.com/crabs".to_string(),
+            summary_text: "Crabs walk sideways".to_string(),
+            ..Document::default()
+        };
+        let score = |word: &str, tf: f64| {
+            TfIdfScore::new(
+                word.to_string(),
+                document.get_id(),
+                document.url.clone(),
+                tf,
+                2.0,
+            )
+        };
+        db.add_document(
+            document.clone(),
+            vec![score("crab", 0.25), score("walk", 0.1)],
+        );
+
+        let query = "crab walk";
+        let parsed = parse(query).unwrap();
+        let stats = term_stats(&db, parsed.terms()).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let index = Index::open(dir.path()).unwrap();
+        let found = search(&db, &index, &parsed, 0, 10).await.unwrap();
+        let out = format_results(query, &stats, &found);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "\"crab walk\": 1 of 1 matching documents");
+        assert!(lines[1].starts_with("  crab ") && lines[1].ends_with("idf 2.0000 in 1 documents"));
+        assert_eq!(lines[3], "  1. 0.7000  Crabs");
+        assert_eq!(lines[4], "     https://example.com/crabs");
+        assert_eq!(lines[5], "     crab 0.5000 + walk 0.2000");
+        assert_eq!(lines[6], "     Crabs walk sideways");
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use common::models::{Document, DocumentMetadata, TfIdfScore};
+use mongodb::bson::oid::ObjectId;
+
+use crate::storage::{Storage, StorageError};
+
+// Keeps the scores of every term looked up so far, so a session running
+// many queries reads each term from the database only once. Documents are
+// always read through.
+pub struct CachedStorage {
+    db: Arc<dyn Storage>,
+    scores: Mutex<HashMap<String, Arc<Vec<TfIdfScore>>>>,
+}
+
+impl CachedStorage {
+    pub fn new(db: Arc<dyn Storage>) -> CachedStorage {
+        CachedStorage {
+            db,
+            scores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Number of terms loaded so far
+    pub fn len(&self) -> usize {
+        self.scores.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl Storage for CachedStorage {
+    async fn scores_for(&self, word: &str) -> Result<Vec<TfIdfScore>, StorageError> {
+        let cached = self.scores.lock().unwrap().get(word).cloned();
+        let scores = match cached {
+            Some(scores) => scores,
+            None => {
+                let scores = Arc::new(self.db.scores_for(word).await?);
+                self.scores
+                    .lock()
+                    .unwrap()
+                    .insert(word.to_string(), Arc::clone(&scores));
+                scores
+            }
+        };
+        Ok(scores.as_ref().clone())
+    }
+
+    async fn documents_metadata(
+        &self,
+        ids: &[ObjectId],
+    ) -> Result<Vec<DocumentMetadata>, StorageError> {
+        self.db.documents_metadata(ids).await
+    }
+
+    async fn summaries(&self, ids: &[ObjectId]) -> Result<Vec<(ObjectId, String)>, StorageError> {
+        self.db.summaries(ids).await
+    }
+
+    async fn find_document(&self, id: ObjectId) -> Result<Option<Document>, StorageError> {
+        self.db.find_document(id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStorage;
+
+    #[tokio::test]
+    async fn test_scores_are_read_once() {
+        let memory = Arc::new(MemoryStorage::new());
+        let cache = CachedStorage::new(memory.clone());
+        let add = |word: &str| {
+            let document = Document::default();
+            let score = TfIdfScore::new(
+                word.to_string(),
+                document.get_id(),
+                document.url.clone(),
+                1.0,
+                1.0,
+            );
+            memory.add_document(document, vec![score]);
+        };
+
+        add("crab");
+        assert_eq!(cache.scores_for("crab").await.unwrap().len(), 1);
+        // Scores written after the first lookup are not seen
+        add("crab");
+        assert_eq!(cache.scores_for("crab").await.unwrap().len(), 1);
+        assert_eq!(memory.scores_for("crab").await.unwrap().len(), 2);
+        assert_eq!(cache.len(), 1);
+        // Documents are not cached
+        let id = Document::default().get_id();
+        assert!(cache.find_document(id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,76 @@
+use common::{
+    db::{connect, Collections},
+    models::{DocumentMetadata, TfIdfScore},
+    schema,
+};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    error::Error,
+};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Summary {
+    _id: ObjectId,
+    summary_text: String,
+}
+
+pub struct Database {
+    pub collections: Collections,
+}
+
+impl Database {
+    pub async fn new() -> Database {
+        let db = connect().await;
+        schema::setup(&db, &schema::shared_indexes()).await;
+        Database {
+            collections: Collections::new(&db),
+        }
+    }
+
+    // Served by the `word_1_tf_idf_-1` index
+    pub async fn scores_for(&self, word: &str) -> Result<Vec<TfIdfScore>, Error> {
+        let cursor = self
+            .collections
+            .tf_idf_scores
+            .find(doc! { "word": word })
+            .await?;
+        cursor.try_collect().await
+    }
+
+    pub async fn documents_metadata(
+        &self,
+        ids: &[ObjectId],
+    ) -> Result<Vec<DocumentMetadata>, Error> {
+        let projection = doc! {
+            "_id": 1,
+            "url": 1,
+            "title": 1,
+            "description": 1,
+        };
+        let cursor = self
+            .collections
+            .documents
+            .clone_with_type::<DocumentMetadata>()
+            .find(doc! { "_id": { "$in": ids } })
+            .projection(projection)
+            .await?;
+        cursor.try_collect().await
+    }
+
+    pub async fn summaries(&self, ids: &[ObjectId]) -> Result<Vec<(ObjectId, String)>, Error> {
+        let cursor = self
+            .collections
+            .documents
+            .clone_with_type::<Summary>()
+            .find(doc! { "_id": { "$in": ids } })
+            .projection(doc! { "_id": 1, "summary_text": 1 })
+            .await?;
+        let summaries: Vec<Summary> = cursor.try_collect().await?;
+        Ok(summaries
+            .into_iter()
+            .map(|summary| (summary._id, summary.summary_text))
+            .collect())
+    }
+}
@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use common::{
+    models::{Document, DocumentMetadata, TfIdfScore},
+    tables::{score_prefix, DOCUMENTS, TF_IDF_SCORES},
+};
+use mongodb::bson::{self, oid::ObjectId};
+
+use crate::storage::{Storage, StorageError};
+
+// The redb file the indexer and the TF-IDF job write to
+pub struct EmbeddedStorage {
+    db: redb::Database,
+}
+
+impl EmbeddedStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<EmbeddedStorage, StorageError> {
+        let db = redb::Database::create(path)?;
+        let txn = db.begin_write()?;
+        txn.open_table(DOCUMENTS)?;
+        txn.open_table(TF_IDF_SCORES)?;
+        txn.commit()?;
+        Ok(EmbeddedStorage { db })
+    }
+}
+
+#[async_trait]
+impl Storage for EmbeddedStorage {
+    // The scores of a word are one range of keys
+    async fn scores_for(&self, word: &str) -> Result<Vec<TfIdfScore>, StorageError> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(TF_IDF_SCORES)?;
+        let prefix = score_prefix(word);
+        let mut scores = Vec::new();
+        for entry in table.range(prefix.as_str()..)? {
+            let (key, bytes) = entry?;
+            if !key.value().starts_with(&prefix) {
+                break;
+            }
+            scores.push(bson::from_slice(bytes.value())?);
+        }
+        Ok(scores)
+    }
+
+    async fn documents_metadata(
+        &self,
+        ids: &[ObjectId],
+    ) -> Result<Vec<DocumentMetadata>, StorageError> {
+        let txn = self.db.begin_read()?;
+        let documents = txn.open_table(DOCUMENTS)?;
+        let mut metadata = Vec::new();
+        for id in ids {
+            if let Some(bytes) = documents.get(id.to_hex().as_str())? {
+                metadata.push(bson::from_slice(bytes.value())?);
+            }
+        }
+        Ok(metadata)
+    }
+
+    async fn summaries(&self, ids: &[ObjectId]) -> Result<Vec<(ObjectId, String)>, StorageError> {
+        let txn = self.db.begin_read()?;
+        let documents = txn.open_table(DOCUMENTS)?;
+        let mut summaries = Vec::new();
+        for id in ids {
+            if let Some(bytes) = documents.get(id.to_hex().as_str())? {
+                let document: Document = bson::from_slice(bytes.value())?;
+                summaries.push((*id, document.summary_text));
+            }
+        }
+        Ok(summaries)
+    }
+
+    async fn find_document(&self, id: ObjectId) -> Result<Option<Document>, StorageError> {
+        let txn = self.db.begin_read()?;
+        let documents = txn.open_table(DOCUMENTS)?;
+        match documents.get(id.to_hex().as_str())? {
+            Some(bytes) => Ok(Some(bson::from_slice(bytes.value())?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::models::Document;
+    use common::tables::score_key;
+
+    #[tokio::test]
+    async fn test_embedded_reads_scores_and_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = EmbeddedStorage::open(dir.path().join("test.redb")).unwrap();
+        let document = Document {
+            url: "https://example.com/".to_string(),
+            ..Document::default()
+        };
+        let crab = TfIdfScore::new(
+            "crab".to_string(),
+            document.get_id(),
+            document.url.clone(),
+            0.5,
+            2.0,
+        );
+        let other = |word: &str| TfIdfScore {
+            word: word.to_string(),
+            _id: ObjectId::new(),
+            ..crab.clone()
+        };
+        {
+            let txn = storage.db.begin_write().unwrap();
+            let id = document.get_id().to_hex();
+            let bytes = bson::to_vec(&document).unwrap();
+            txn.open_table(DOCUMENTS)
+                .unwrap()
+                .insert(id.as_str(), bytes.as_slice())
+                .unwrap();
+            let mut table = txn.open_table(TF_IDF_SCORES).unwrap();
+            for score in [crab.clone(), other("lobster"), other("crabs"), other("cra")] {
+                let bytes = bson::to_vec(&score).unwrap();
+                table
+                    .insert(score_key(&score).as_str(), bytes.as_slice())
+                    .unwrap();
+            }
+            drop(table);
+            txn.commit().unwrap();
+        }
+
+        // Words sharing a prefix are kept apart
+        let scores = storage.scores_for("crab").await.unwrap();
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0].tf_idf, 1.0);
+        assert!(storage.scores_for("cr").await.unwrap().is_empty());
+        let metadata = storage
+            .documents_metadata(&[document.get_id(), ObjectId::new()])
+            .await
+            .unwrap();
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata[0].url, document.url);
+        let summaries = storage.summaries(&[document.get_id()]).await.unwrap();
+        assert_eq!(
+            summaries,
+            [(document.get_id(), document.summary_text.clone())]
+        );
+        let found = storage.find_document(document.get_id()).await.unwrap();
+        assert_eq!(found.unwrap().title, document.title);
+        assert!(storage
+            .find_document(ObjectId::new())
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
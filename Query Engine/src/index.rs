@@ -0,0 +1,69 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use common::segment::{index_dir, segment_files, Index, SegmentError};
+
+// The segments the indexer writes, for phrase and field matching. The open
+// index is kept until a flush or a merge changes the set of segment files,
+// so a long running server sees new pages without reopening every segment
+// for every query.
+pub struct IndexReader {
+    dir: PathBuf,
+    current: Mutex<Option<Arc<Index>>>,
+}
+
+impl IndexReader {
+    pub fn new(dir: PathBuf) -> IndexReader {
+        IndexReader {
+            dir,
+            current: Mutex::new(None),
+        }
+    }
+
+    // Reads from INDEX_DIR, the same directory the indexer writes to
+    pub fn from_env() -> IndexReader {
+        IndexReader::new(index_dir())
+    }
+
+    pub fn index(&self) -> Result<Arc<Index>, SegmentError> {
+        let files = segment_files(&self.dir)?;
+        let mut current = self.current.lock().unwrap();
+        if let Some(index) = current.as_ref() {
+            if index.files() == files.as_slice() {
+                return Ok(Arc::clone(index));
+            }
+        }
+        let index = Arc::new(Index::open(&self.dir)?);
+        *current = Some(Arc::clone(&index));
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{models::Document, segment::SegmentWriter};
+
+    use super::*;
+
+    #[test]
+    fn test_index_is_reopened_when_segments_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let reader = IndexReader::new(dir.path().to_path_buf());
+        assert_eq!(reader.index().unwrap().doc_count(), 0);
+
+        let document = Document::default();
+        let mut writer = SegmentWriter::new();
+        writer.add(&document, vec![]);
+        writer.write(dir.path()).unwrap();
+        let first = reader.index().unwrap();
+        assert_eq!(first.doc_count(), 1);
+        assert!(Arc::ptr_eq(&first, &reader.index().unwrap()));
+
+        let mut writer = SegmentWriter::new();
+        writer.remove(document.get_id());
+        writer.write(dir.path()).unwrap();
+        assert_eq!(reader.index().unwrap().doc_count(), 0);
+    }
+}
@@ -0,0 +1,10 @@
+// Answers queries over the scores the TF-IDF job writes
+pub mod api;
+pub mod cache;
+pub mod db;
+pub mod embedded;
+pub mod index;
+pub mod memory;
+pub mod query;
+pub mod search;
+pub mod storage;
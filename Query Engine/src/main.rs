@@ -0,0 +1,23 @@
+use std::sync::Arc;
+
+use query_engine::{api, index::IndexReader, storage::open_storage};
+use tokio::net::TcpListener;
+
+// Serves the search API on SERVER_ADDR
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+    let addr = dotenv::var("SERVER_ADDR").unwrap_or_else(|_| "127.0.0.1:3000".to_string());
+    let db = open_storage().await;
+    let index = Arc::new(IndexReader::from_env());
+    let listener = match TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            panic!("{}", e);
+        }
+    };
+    println!("Serving search on http://{}", addr);
+    if let Err(e) = axum::serve(listener, api::router(db, index)).await {
+        println!("Server stopped: {}", e);
+    }
+}
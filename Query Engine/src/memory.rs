@@ -0,0 +1,88 @@
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use common::models::{Document, DocumentMetadata, TfIdfScore};
+use mongodb::bson::oid::ObjectId;
+
+use crate::storage::{Storage, StorageError};
+
+#[derive(Default)]
+struct Tables {
+    documents: Vec<Document>,
+    tf_idf_scores: Vec<TfIdfScore>,
+}
+
+// Storage that lives only as long as the process, for tests and dry runs
+#[derive(Default)]
+pub struct MemoryStorage {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap()
+    }
+
+    // Loads a scored page the way the TF-IDF job would have stored it
+    pub fn add_document(&self, document: Document, scores: Vec<TfIdfScore>) {
+        let mut tables = self.tables();
+        tables.documents.push(document);
+        tables.tf_idf_scores.extend(scores);
+    }
+
+    fn matching(&self, ids: &[ObjectId]) -> Vec<Document> {
+        self.tables()
+            .documents
+            .iter()
+            .filter(|document| ids.contains(&document.get_id()))
+            .cloned()
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn scores_for(&self, word: &str) -> Result<Vec<TfIdfScore>, StorageError> {
+        let tables = self.tables();
+        Ok(tables
+            .tf_idf_scores
+            .iter()
+            .filter(|score| score.word == word)
+            .cloned()
+            .collect())
+    }
+
+    async fn documents_metadata(
+        &self,
+        ids: &[ObjectId],
+    ) -> Result<Vec<DocumentMetadata>, StorageError> {
+        Ok(self
+            .matching(ids)
+            .into_iter()
+            .map(|document| {
+                DocumentMetadata::new(
+                    document.get_id(),
+                    document.url,
+                    document.title,
+                    document.description,
+                )
+            })
+            .collect())
+    }
+
+    async fn summaries(&self, ids: &[ObjectId]) -> Result<Vec<(ObjectId, String)>, StorageError> {
+        Ok(self
+            .matching(ids)
+            .into_iter()
+            .map(|document| (document.get_id(), document.summary_text))
+            .collect())
+    }
+
+    async fn find_document(&self, id: ObjectId) -> Result<Option<Document>, StorageError> {
+        Ok(self.matching(&[id]).into_iter().next())
+    }
+}
@@ -0,0 +1,496 @@
+// The query language. Words are cleaned like page text, and
+//
+//     +crab  crab AND rust   require a clause
+//     -crab  NOT crab        exclude it
+//     crab OR rust           match either
+//     "hermit crab"          match the words next to each other
+//     (crab OR lobster) -rust
+//     title:crab             match in one field: title, description or body,
+//     title:"hermit crab"    also for phrases and groups
+//     site:docs.rs           only pages on a host or its subdomains
+//     inurl:api              only pages whose URL contains the text
+//
+// Clauses next to each other without an operator are alternatives, so a
+// plain query matches any of its words. Next to a required clause they only
+// rank the documents that match higher. `site:` and `inurl:` narrow down
+// what the rest of the query matches and match nothing on their own. NOT
+// binds tightest, then AND, then OR. Operators are only recognised in
+// capitals.
+use std::{fmt, iter::Peekable, vec::IntoIter};
+
+use common::{models::Field, text::tokenize};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Term(String),
+    // Words that occur one right after another in the same field
+    Phrase(Vec<String>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    // Matches nothing on its own, only removes matches from an `And`
+    Not(Box<Query>),
+    // Matches what `query` does. Documents the optional clauses match as
+    // well rank higher.
+    Boosted {
+        query: Box<Query>,
+        optional: Vec<Query>,
+    },
+    // Matches of the query where its words occur in the field
+    InField(Field, Box<Query>),
+    // Pages on this host or one of its subdomains
+    Site(String),
+    // Pages whose URL contains this text
+    InUrl(String),
+}
+
+impl Query {
+    // Terms that add to the score of a match, without repeats. Terms that
+    // are only excluded are left out.
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.positive_terms(&mut terms);
+        terms
+    }
+
+    // Whether the query narrows down the matches of others instead of
+    // matching documents itself
+    pub fn is_filter(&self) -> bool {
+        matches!(self, Query::Site(_) | Query::InUrl(_))
+    }
+
+    fn positive_terms(&self, terms: &mut Vec<String>) {
+        match self {
+            Query::Term(term) => push_new(terms, term),
+            Query::Phrase(words) => words.iter().for_each(|word| push_new(terms, word)),
+            Query::And(clauses) | Query::Or(clauses) => clauses
+                .iter()
+                .for_each(|clause| clause.positive_terms(terms)),
+            Query::Not(_) | Query::Site(_) | Query::InUrl(_) => {}
+            Query::Boosted { query, optional } => {
+                query.positive_terms(terms);
+                optional
+                    .iter()
+                    .for_each(|clause| clause.positive_terms(terms));
+            }
+            Query::InField(_, query) => query.positive_terms(terms),
+        }
+    }
+
+    // Calls `visit` on this query and every clause in it
+    pub fn walk(&self, visit: &mut impl FnMut(&Query)) {
+        visit(self);
+        match self {
+            Query::Term(_) | Query::Phrase(_) | Query::Site(_) | Query::InUrl(_) => {}
+            Query::And(clauses) | Query::Or(clauses) => {
+                clauses.iter().for_each(|clause| clause.walk(visit))
+            }
+            Query::Not(clause) | Query::InField(_, clause) => clause.walk(visit),
+            Query::Boosted { query, optional } => {
+                query.walk(visit);
+                optional.iter().for_each(|clause| clause.walk(visit));
+            }
+        }
+    }
+}
+
+fn push_new(terms: &mut Vec<String>, term: &str) {
+    if !terms.iter().any(|seen| seen == term) {
+        terms.push(term.to_string());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError(pub String);
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Open,
+    Close,
+    Plus,
+    Minus,
+    And,
+    Or,
+    Not,
+}
+
+fn lex(query: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                // An unclosed quote runs to the end of the query
+                let quoted: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                tokens.push(Token::Quoted(quoted));
+            }
+            '+' | '-' => {
+                chars.next();
+                // Only a prefix when something follows it directly
+                match chars.peek() {
+                    Some(next) if !next.is_whitespace() && *next != ')' => {
+                        tokens.push(if c == '+' { Token::Plus } else { Token::Minus })
+                    }
+                    _ => {}
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+    tokens
+}
+
+// How a clause next to others counts towards a match
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Occur {
+    Should,
+    Must,
+    MustNot,
+}
+
+// A clause that is `None` was only stop words and is dropped
+type Clause = Option<(Occur, Query)>;
+
+struct Parser {
+    tokens: Peekable<IntoIter<Token>>,
+}
+
+impl Parser {
+    // clause*
+    fn sequence(&mut self) -> Result<Option<Query>, QueryError> {
+        let mut clauses = Vec::new();
+        while !matches!(self.tokens.peek(), None | Some(Token::Close)) {
+            if let Some(clause) = self.or()? {
+                clauses.push(clause);
+            }
+        }
+        Ok(combine(clauses))
+    }
+
+    // and (OR and)*
+    fn or(&mut self) -> Result<Clause, QueryError> {
+        let mut clauses = vec![self.and()?];
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            clauses.push(self.and()?);
+        }
+        Ok(join(clauses, Query::Or))
+    }
+
+    // unary (AND unary)*
+    fn and(&mut self) -> Result<Clause, QueryError> {
+        let mut clauses = vec![self.unary()?];
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            clauses.push(self.unary()?);
+        }
+        Ok(join(clauses, Query::And))
+    }
+
+    // (NOT | + | -)* primary
+    fn unary(&mut self) -> Result<Clause, QueryError> {
+        match self.tokens.peek() {
+            Some(Token::Not) | Some(Token::Minus) => {
+                self.tokens.next();
+                Ok(self.unary()?.map(|(occur, query)| match occur {
+                    // Excluding an exclusion requires the clause
+                    Occur::MustNot => (Occur::Must, query),
+                    _ => (Occur::MustNot, query),
+                }))
+            }
+            Some(Token::Plus) => {
+                self.tokens.next();
+                Ok(self.unary()?.map(|(occur, query)| match occur {
+                    Occur::Should => (Occur::Must, query),
+                    occur => (occur, query),
+                }))
+            }
+            _ => Ok(self.primary()?.map(|query| (Occur::Should, query))),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Option<Query>, QueryError> {
+        match self.tokens.next() {
+            Some(Token::Word(word)) => match word.split_once(':') {
+                Some((prefix, value)) if PREFIXES.contains(&prefix) => self.prefixed(prefix, value),
+                _ => Ok(words(&word)),
+            },
+            Some(Token::Quoted(text)) => Ok(words(&text)),
+            Some(Token::Open) => {
+                let query = self.sequence()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(query),
+                    _ => Err(QueryError("missing `)`".to_string())),
+                }
+            }
+            Some(Token::Close) => Err(QueryError("unexpected `)`".to_string())),
+            Some(_) => Err(QueryError(
+                "`AND` and `OR` need a clause on each side".to_string(),
+            )),
+            None => Err(QueryError("expected a word at the end".to_string())),
+        }
+    }
+
+    // What follows `title:` and the like. A field can be followed by a
+    // word, quoted text or a group.
+    fn prefixed(&mut self, prefix: &str, value: &str) -> Result<Option<Query>, QueryError> {
+        let field = match prefix {
+            "title" => Field::Title,
+            "description" => Field::Description,
+            "body" => Field::Body,
+            _ if value.is_empty() => {
+                return Err(QueryError(format!("`{}:` needs a value", prefix)));
+            }
+            "site" => {
+                let host = value.trim_start_matches('.').to_ascii_lowercase();
+                return Ok(Some(Query::Site(host)));
+            }
+            _ => return Ok(Some(Query::InUrl(value.to_ascii_lowercase()))),
+        };
+        let query = if value.is_empty() {
+            self.primary()?
+        } else {
+            words(value)
+        };
+        Ok(query.map(|query| Query::InField(field, Box::new(query))))
+    }
+}
+
+const PREFIXES: [&str; 5] = ["title", "description", "body", "site", "inurl"];
+
+// A word or quoted text as the indexer would have stored it. Text that
+// cleans into several terms, like `rust-lang`, must match as a phrase.
+fn words(text: &str) -> Option<Query> {
+    // Stop words are only kept at the very end of a page, never mid-query
+    let mut terms = tokenize(format!("{} ", text));
+    match terms.len() {
+        0 => None,
+        1 => terms.pop().map(Query::Term),
+        _ => Some(Query::Phrase(terms)),
+    }
+}
+
+// Joins the clauses of an AND or OR. Exclusions become `Not` clauses, and a
+// required clause is no different from any other inside an operator.
+fn join(clauses: Vec<Clause>, operator: fn(Vec<Query>) -> Query) -> Clause {
+    let mut clauses: Vec<(Occur, Query)> = clauses.into_iter().flatten().collect();
+    if clauses.len() <= 1 {
+        return clauses.pop();
+    }
+    let queries = clauses
+        .into_iter()
+        .map(|(occur, query)| match occur {
+            Occur::MustNot => Query::Not(Box::new(query)),
+            _ => query,
+        })
+        .collect();
+    Some((Occur::Should, operator(queries)))
+}
+
+// Turns clauses standing next to each other into one query. Filters always
+// apply, with or without a `+`.
+fn combine(clauses: Vec<(Occur, Query)>) -> Option<Query> {
+    let mut must = Vec::new();
+    let mut should = Vec::new();
+    let mut must_not = Vec::new();
+    let mut filters = Vec::new();
+    for (occur, query) in clauses {
+        match occur {
+            Occur::MustNot => must_not.push(Query::Not(Box::new(query))),
+            _ if query.is_filter() => filters.push(query),
+            Occur::Must => must.push(query),
+            Occur::Should => should.push(query),
+        }
+    }
+    let optional = if must.is_empty() {
+        if !should.is_empty() {
+            must.push(one_or(should, Query::Or));
+        }
+        Vec::new()
+    } else {
+        should
+    };
+    must.extend(filters);
+    must.extend(must_not);
+    if must.is_empty() {
+        return None;
+    }
+    let query = one_or(must, Query::And);
+    if optional.is_empty() {
+        return Some(query);
+    }
+    Some(Query::Boosted {
+        query: Box::new(query),
+        optional,
+    })
+}
+
+fn one_or(mut queries: Vec<Query>, operator: fn(Vec<Query>) -> Query) -> Query {
+    if queries.len() == 1 {
+        queries.remove(0)
+    } else {
+        operator(queries)
+    }
+}
+
+// Parses a query typed by a user. A query of only stop words matches
+// nothing.
+pub fn parse(query: &str) -> Result<Query, QueryError> {
+    let mut parser = Parser {
+        tokens: lex(query).into_iter().peekable(),
+    };
+    let parsed = parser.sequence()?;
+    if parser.tokens.next().is_some() {
+        return Err(QueryError("unexpected `)`".to_string()));
+    }
+    Ok(parsed.unwrap_or(Query::Or(Vec::new())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(word: &str) -> Query {
+        Query::Term(word.to_string())
+    }
+
+    fn not(query: Query) -> Query {
+        Query::Not(Box::new(query))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("Crab, the rust").unwrap(),
+            Query::Or(vec![term("crab"), term("rust")])
+        );
+        assert_eq!(
+            parse("crab AND rust OR lobster").unwrap(),
+            Query::Or(vec![
+                Query::And(vec![term("crab"), term("rust")]),
+                term("lobster")
+            ])
+        );
+        assert_eq!(
+            parse("crab -rust NOT lobster").unwrap(),
+            Query::And(vec![term("crab"), not(term("rust")), not(term("lobster"))])
+        );
+        assert_eq!(
+            parse("+crab rust -(lobster OR shrimp)").unwrap(),
+            Query::Boosted {
+                query: Box::new(Query::And(vec![
+                    term("crab"),
+                    not(Query::Or(vec![term("lobster"), term("shrimp")]))
+                ])),
+                optional: vec![term("rust")],
+            }
+        );
+        assert_eq!(
+            parse("\"Hermit crabs\" rust-lang").unwrap(),
+            Query::Or(vec![
+                Query::Phrase(vec!["hermit".to_string(), "crabs".to_string()]),
+                Query::Phrase(vec!["rust".to_string(), "lang".to_string()]),
+            ])
+        );
+        // Stop words leave nothing to join
+        assert_eq!(parse("crab AND the").unwrap(), term("crab"));
+        assert_eq!(parse("NOT -crab").unwrap(), term("crab"));
+        assert_eq!(parse("the and").unwrap(), Query::Or(Vec::new()));
+        assert_eq!(parse("crab - rust").unwrap(), parse("crab rust").unwrap());
+    }
+
+    #[test]
+    fn test_parse_fields_and_filters() {
+        let title = |query: Query| Query::InField(Field::Title, Box::new(query));
+        assert_eq!(
+            parse("title:Rust site:Docs.rs inurl:API").unwrap(),
+            Query::And(vec![
+                title(term("rust")),
+                Query::Site("docs.rs".to_string()),
+                Query::InUrl("api".to_string()),
+            ])
+        );
+        assert_eq!(
+            parse("title:\"hermit crab\" body:(crab OR lobster)").unwrap(),
+            Query::Or(vec![
+                title(Query::Phrase(vec![
+                    "hermit".to_string(),
+                    "crab".to_string()
+                ])),
+                Query::InField(
+                    Field::Body,
+                    Box::new(Query::Or(vec![term("crab"), term("lobster")]))
+                ),
+            ])
+        );
+        assert_eq!(
+            parse("crab -site:example.com").unwrap(),
+            Query::And(vec![
+                term("crab"),
+                not(Query::Site("example.com".to_string()))
+            ])
+        );
+        // Anything else with a colon is plain text
+        assert_eq!(
+            parse("https://docs.rs").unwrap(),
+            Query::Phrase(vec![
+                "https".to_string(),
+                "docs".to_string(),
+                "rs".to_string()
+            ])
+        );
+        assert!(parse("site:").is_err());
+        assert!(parse("title:").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("(crab rust").is_err());
+        assert!(parse("crab) rust").is_err());
+        assert!(parse("crab OR").is_err());
+        assert!(parse("AND crab").is_err());
+        assert!(parse("crab OR OR rust").is_err());
+    }
+
+    #[test]
+    fn test_terms() {
+        let query = parse("+crab \"hermit crab\" -rust OR lobster").unwrap();
+        assert_eq!(query.terms(), ["crab", "hermit", "lobster"]);
+        let query = parse("title:shrimp site:shrimp.com").unwrap();
+        assert_eq!(query.terms(), ["shrimp"]);
+    }
+}
@@ -0,0 +1,653 @@
+use std::collections::HashMap;
+
+use common::{
+    models::{DocumentMetadata, Field, Positions, TfIdfScore},
+    postings::contains_phrase,
+    segment::Index,
+};
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    query::Query,
+    storage::{Storage, StorageError},
+};
+
+// A document matching a query and how well it matches
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub document: DocumentMetadata,
+    pub score: f64,
+    // What each query term found in the document added to `score`
+    pub contributions: Vec<(String, f64)>,
+    // The part of the page's summary where the query matched
+    pub snippet: String,
+}
+
+// One page of results, with how many documents matched in all
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResults {
+    pub total: usize,
+    pub results: Vec<SearchResult>,
+}
+
+const SNIPPET_WORDS: usize = 30;
+
+// A document's total score and what each term added to it
+type Match = (f64, Vec<(String, f64)>);
+
+// What each term of a query added to the score of a matching document
+type Matches = HashMap<ObjectId, Vec<(String, f64)>>;
+
+// The scores of every term in a query, where in each document the terms
+// that need positions occur, and the fields each of its phrases occurs in,
+// read up front so the query can be evaluated in one pass
+#[derive(Default)]
+struct Postings {
+    scores: HashMap<String, HashMap<ObjectId, TfIdfScore>>,
+    positions: HashMap<String, HashMap<ObjectId, Positions>>,
+    phrases: HashMap<Vec<String>, HashMap<ObjectId, Vec<Field>>>,
+}
+
+impl Postings {
+    async fn load(
+        db: &dyn Storage,
+        index: &Index,
+        query: &Query,
+    ) -> Result<Postings, StorageError> {
+        let mut terms = Vec::new();
+        let mut phrases = Vec::new();
+        // Phrases and fielded terms are matched on positions from the index
+        let mut positional = Vec::new();
+        query.walk(&mut |clause| match clause {
+            Query::Term(term) => terms.push(term.clone()),
+            Query::Phrase(words) => {
+                terms.extend(words.iter().cloned());
+                positional.extend(words.iter().cloned());
+                phrases.push(words.clone());
+            }
+            Query::InField(_, query) => query.walk(&mut |clause| {
+                if let Query::Term(term) = clause {
+                    positional.push(term.clone());
+                }
+            }),
+            _ => {}
+        });
+
+        let mut postings = Postings::default();
+        for term in terms {
+            if postings.scores.contains_key(&term) {
+                continue;
+            }
+            let scores = db
+                .scores_for(&term)
+                .await?
+                .into_iter()
+                .map(|score| (score.document_id, score))
+                .collect();
+            postings.scores.insert(term, scores);
+        }
+        for term in positional {
+            if postings.positions.contains_key(&term) {
+                continue;
+            }
+            let positions = index
+                .postings(&term)?
+                .into_iter()
+                .map(|(document, posting)| (document.id, posting.positions))
+                .collect();
+            postings.positions.insert(term, positions);
+        }
+        for words in phrases {
+            let found = postings.phrase(&words);
+            postings.phrases.insert(words, found);
+        }
+        Ok(postings)
+    }
+
+    // The fields in which `words` occur in order, for every document that
+    // has a score for each of them
+    fn phrase(&self, words: &[String]) -> HashMap<ObjectId, Vec<Field>> {
+        let in_order = |id: &ObjectId, field: Field| {
+            let terms: Option<Vec<&[u32]>> = words
+                .iter()
+                .map(|word| self.positions[word].get(id).map(|p| p.get(field)))
+                .collect();
+            terms.is_some_and(|terms| contains_phrase(&terms))
+        };
+        self.scores[&words[0]]
+            .keys()
+            .filter(|id| words.iter().all(|word| self.scores[word].contains_key(id)))
+            .filter_map(|id| {
+                let fields: Vec<Field> = Field::ALL
+                    .into_iter()
+                    .filter(|&field| in_order(id, field))
+                    .collect();
+                (!fields.is_empty()).then_some((*id, fields))
+            })
+            .collect()
+    }
+
+    // Documents containing `term`, in `field` if there is one
+    fn term(&self, term: &str, field: Option<Field>) -> Matches {
+        let in_field = |id: &ObjectId, field: Field| {
+            self.positions
+                .get(term)
+                .and_then(|positions| positions.get(id))
+                .is_some_and(|positions| !positions.get(field).is_empty())
+        };
+        self.scores[term]
+            .iter()
+            .filter(|(id, _)| field.is_none_or(|field| in_field(id, field)))
+            .map(|(id, score)| (*id, vec![(term.to_string(), score.tf_idf)]))
+            .collect()
+    }
+
+    // Whether a matching document passes `filter`. Every score carries the
+    // URL of its document, so any term's will do.
+    fn passes(&self, filter: &Query, id: &ObjectId) -> bool {
+        let Some(score) = self.scores.values().find_map(|scores| scores.get(id)) else {
+            return false;
+        };
+        match filter {
+            Query::Site(site) => on_site(&score.url, site),
+            Query::InUrl(text) => score.url.to_ascii_lowercase().contains(text.as_str()),
+            _ => true,
+        }
+    }
+
+    fn matches(&self, query: &Query, field: Option<Field>) -> Matches {
+        match query {
+            Query::Term(term) => self.term(term, field),
+            Query::Phrase(words) => {
+                let found = &self.phrases[words];
+                let in_field = |id: &ObjectId| {
+                    found
+                        .get(id)
+                        .is_some_and(|fields| field.is_none_or(|field| fields.contains(&field)))
+                };
+                let mut matches = Matches::new();
+                for word in words {
+                    for (id, contributions) in self.term(word, None) {
+                        if in_field(&id) {
+                            add(matches.entry(id).or_default(), contributions);
+                        }
+                    }
+                }
+                matches
+            }
+            Query::And(clauses) => {
+                let mut required = clauses
+                    .iter()
+                    .filter(|clause| !matches!(clause, Query::Not(_)) && !clause.is_filter())
+                    .map(|clause| self.matches(clause, field));
+                // Exclusions and filters alone match nothing
+                let Some(mut matches) = required.next() else {
+                    return Matches::new();
+                };
+                for other in required {
+                    matches.retain(|id, _| other.contains_key(id));
+                    for (id, contributions) in other {
+                        if let Some(found) = matches.get_mut(&id) {
+                            add(found, contributions);
+                        }
+                    }
+                }
+                for clause in clauses {
+                    match clause {
+                        Query::Not(filter) if filter.is_filter() => {
+                            matches.retain(|id, _| !self.passes(filter, id))
+                        }
+                        Query::Not(excluded) => {
+                            for id in self.matches(excluded, field).keys() {
+                                matches.remove(id);
+                            }
+                        }
+                        filter if filter.is_filter() => {
+                            matches.retain(|id, _| self.passes(filter, id))
+                        }
+                        _ => {}
+                    }
+                }
+                matches
+            }
+            Query::Or(clauses) => {
+                let mut matches = Matches::new();
+                for clause in clauses {
+                    for (id, contributions) in self.matches(clause, field) {
+                        add(matches.entry(id).or_default(), contributions);
+                    }
+                }
+                matches
+            }
+            Query::Not(_) | Query::Site(_) | Query::InUrl(_) => Matches::new(),
+            Query::Boosted { query, optional } => {
+                let mut matches = self.matches(query, field);
+                for clause in optional {
+                    for (id, contributions) in self.matches(clause, field) {
+                        if let Some(found) = matches.get_mut(&id) {
+                            add(found, contributions);
+                        }
+                    }
+                }
+                matches
+            }
+            Query::InField(field, query) => self.matches(query, Some(*field)),
+        }
+    }
+}
+
+// Whether `url` is on the host `site` or one of its subdomains
+fn on_site(url: &str, site: &str) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+    let host = url.host_str().unwrap_or_default();
+    host == site
+        || host
+            .strip_suffix(site)
+            .is_some_and(|subdomain| subdomain.ends_with('.'))
+}
+
+// Adds the contributions of terms not counted yet, so a term that appears
+// twice in a query does not count twice
+fn add(into: &mut Vec<(String, f64)>, contributions: Vec<(String, f64)>) {
+    for (term, score) in contributions {
+        if !into.iter().any(|(seen, _)| *seen == term) {
+            into.push((term, score));
+        }
+    }
+}
+
+// Up to `max_words` words of `summary`, starting a little before the first
+// word that matches one of `terms`
+pub fn snippet(summary: &str, terms: &[String], max_words: usize) -> String {
+    let words: Vec<&str> = summary.split_whitespace().collect();
+    let hit = words.iter().position(|word| {
+        let word = word
+            .trim_matches(|c: char| !c.is_alphanumeric())
+            .to_ascii_lowercase();
+        terms.contains(&word)
+    });
+    let start = hit.map_or(0, |hit| hit.saturating_sub(max_words / 4));
+    let end = (start + max_words).min(words.len());
+    let mut snippet = words[start..end].join(" ");
+    if start > 0 {
+        snippet.insert_str(0, "... ");
+    }
+    if end < words.len() {
+        snippet.push_str(" ...");
+    }
+    snippet
+}
+
+// Ranks the documents matching `query` by the sum of their TF-IDF scores
+// for its terms and returns `limit` of them from `offset` on, best first.
+// Phrases and fields are matched on the positions in `index`.
+pub async fn search(
+    db: &dyn Storage,
+    index: &Index,
+    query: &Query,
+    offset: usize,
+    limit: usize,
+) -> Result<SearchResults, StorageError> {
+    let terms = query.terms();
+    let totals: HashMap<ObjectId, Match> = Postings::load(db, index, query)
+        .await?
+        .matches(query, None)
+        .into_iter()
+        .map(|(id, contributions)| {
+            let total = contributions.iter().map(|(_, score)| score).sum();
+            (id, (total, contributions))
+        })
+        .collect();
+
+    // Pages removed since the TF-IDF job last ran still have scores, so they
+    // are dropped before counting and paging
+    let ids: Vec<ObjectId> = totals.keys().copied().collect();
+    let mut documents: HashMap<ObjectId, DocumentMetadata> = db
+        .documents_metadata(&ids)
+        .await?
+        .into_iter()
+        .map(|document| (document._id, document))
+        .collect();
+    let mut ranked: Vec<(ObjectId, Match)> = totals
+        .into_iter()
+        .filter(|(id, _)| documents.contains_key(id))
+        .collect();
+    let total = ranked.len();
+
+    // Ties are broken by id so the same query always gives the same order
+    ranked.sort_by(|a, b| b.1 .0.total_cmp(&a.1 .0).then(a.0.cmp(&b.0)));
+    let ranked: Vec<_> = ranked.into_iter().skip(offset).take(limit).collect();
+
+    let ids: Vec<ObjectId> = ranked.iter().map(|(id, _)| *id).collect();
+    let summaries: HashMap<ObjectId, String> = db.summaries(&ids).await?.into_iter().collect();
+    let results = ranked
+        .into_iter()
+        .filter_map(|(id, (score, contributions))| {
+            let summary = summaries.get(&id).map_or("", String::as_str);
+            Some(SearchResult {
+                document: documents.remove(&id)?,
+                score,
+                contributions,
+                snippet: snippet(summary, &terms, SNIPPET_WORDS),
+            })
+        })
+        .collect();
+    Ok(SearchResults { total, results })
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{
+        models::{Document, TfIdfScore, Words},
+        segment::{SegmentWriter, StoredDocument},
+    };
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{memory::MemoryStorage, query::parse};
+
+    // An index of one segment holding `words`, as the indexer would write it
+    fn index(words: Vec<Words>) -> (TempDir, Index) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut documents: HashMap<ObjectId, Vec<Words>> = HashMap::new();
+        for words in words {
+            documents.entry(words.document).or_default().push(words);
+        }
+        let mut writer = SegmentWriter::new();
+        for (id, words) in documents {
+            let stored = StoredDocument {
+                id,
+                url: String::new(),
+                title: String::new(),
+                description: String::new(),
+                lengths: [0; 3],
+            };
+            writer.add_stored(stored, words);
+        }
+        if !writer.is_empty() {
+            writer.write(dir.path()).unwrap();
+        }
+        let index = Index::open(dir.path()).unwrap();
+        (dir, index)
+    }
+
+    fn add(db: &MemoryStorage, url: &str, scores: &[(&str, f64)]) -> ObjectId {
+        let document = Document {
+            url: url.to_string(),
+            summary_text: "Crabs are decapod crustaceans".to_string(),
+            ..Document::default()
+        };
+        let id = document.get_id();
+        let scores = scores
+            .iter()
+            .map(|(word, tf_idf)| {
+                TfIdfScore::new(word.to_string(), id, url.to_string(), *tf_idf, 1.0)
+            })
+            .collect();
+        db.add_document(document, scores);
+        id
+    }
+
+    #[tokio::test]
+    async fn test_search_ranks_by_summed_scores() {
+        let db = MemoryStorage::new();
+        let (_dir, index) = index(vec![]);
+        let both = add(
+            &db,
+            "https://example.com/both",
+            &[("crab", 0.2), ("rust", 0.2)],
+        );
+        let crab = add(&db, "https://example.com/crab", &[("crab", 0.3)]);
+        add(&db, "https://example.com/other", &[("lobster", 0.9)]);
+
+        // Cleaned like page text: case, punctuation and stop words go
+        let found = search(&db, &index, &parse("The Crab, and RUST!").unwrap(), 0, 10)
+            .await
+            .unwrap();
+        assert_eq!(found.total, 2);
+        let ids: Vec<ObjectId> = found.results.iter().map(|r| r.document._id).collect();
+        assert_eq!(ids, [both, crab]);
+        assert!((found.results[0].score - 0.4).abs() < 1e-9);
+        assert_eq!(found.results[1].document.url, "https://example.com/crab");
+        assert_eq!(found.results[0].snippet, "Crabs are decapod crustaceans");
+        assert_eq!(
+            found.results[0].contributions,
+            [("crab".to_string(), 0.2), ("rust".to_string(), 0.2)]
+        );
+
+        let second = search(&db, &index, &parse("crab rust").unwrap(), 1, 1)
+            .await
+            .unwrap();
+        assert_eq!(second.total, 2);
+        assert_eq!(second.results.len(), 1);
+        assert_eq!(second.results[0].document._id, crab);
+        // Repeating a term does not count it twice
+        let repeated = search(&db, &index, &parse("crab crab").unwrap(), 0, 10)
+            .await
+            .unwrap();
+        assert_eq!(repeated.results[0].document._id, crab);
+        assert_eq!(
+            search(&db, &index, &parse("the").unwrap(), 0, 10)
+                .await
+                .unwrap()
+                .total,
+            0
+        );
+        assert!(search(&db, &index, &parse("shrimp").unwrap(), 0, 10)
+            .await
+            .unwrap()
+            .results
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_boolean_queries() {
+        let db = MemoryStorage::new();
+        let both = add(
+            &db,
+            "https://example.com/both",
+            &[("hermit", 0.1), ("crab", 0.2), ("rust", 0.4)],
+        );
+        let crab = add(&db, "https://example.com/crab", &[("crab", 0.3)]);
+        let lobster = add(
+            &db,
+            "https://example.com/lobster",
+            &[("lobster", 0.5), ("crab", 0.1), ("hermit", 0.1)],
+        );
+        // "hermit crab" in the body of `both`, "crab ... hermit" in `lobster`
+        let body = |document: ObjectId, word: &str, body: Vec<u32>| {
+            let mut words = Words::new(document, word.to_string(), body.len() as i32);
+            words.positions.body = body;
+            words
+        };
+        let (_dir, index) = index(vec![
+            body(both, "hermit", vec![3]),
+            body(both, "crab", vec![4]),
+            body(lobster, "crab", vec![0]),
+            body(lobster, "hermit", vec![5]),
+        ]);
+        let ids = |query: &str| {
+            let query = parse(query).unwrap();
+            let (db, index) = (&db, &index);
+            async move {
+                let found = search(db, index, &query, 0, 10).await.unwrap();
+                found
+                    .results
+                    .iter()
+                    .map(|result| result.document._id)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(ids("crab -rust").await, [crab, lobster]);
+        assert_eq!(ids("crab AND NOT (rust OR lobster)").await, [crab]);
+        assert_eq!(ids("+crab +hermit").await, [both, lobster]);
+        assert_eq!(ids("\"hermit crab\"").await, [both]);
+        assert_eq!(ids("\"crab hermit\"").await, Vec::<ObjectId>::new());
+        assert_eq!(ids("lobster OR rust").await, [lobster, both]);
+        assert_eq!(ids("-crab").await, Vec::<ObjectId>::new());
+
+        // Optional terms only reorder what the required ones match
+        let found = search(&db, &index, &parse("+crab lobster").unwrap(), 0, 10)
+            .await
+            .unwrap();
+        assert_eq!(found.total, 3);
+        assert_eq!(found.results[0].document._id, lobster);
+        assert!((found.results[0].score - 0.6).abs() < 1e-9);
+        assert_eq!(
+            found.results[0].contributions,
+            [("crab".to_string(), 0.1), ("lobster".to_string(), 0.5)]
+        );
+    }
+
+    #[test]
+    fn test_snippet_starts_near_the_match() {
+        let summary = (0..100)
+            .map(|i| format!("w{}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let terms = vec!["w50".to_string()];
+        let middle = snippet(&summary, &terms, 8);
+        assert_eq!(middle, "... w48 w49 w50 w51 w52 w53 w54 w55 ...");
+        let start = snippet(&summary, &["missing".to_string()], 3);
+        assert_eq!(start, "w0 w1 w2 ...");
+        assert_eq!(snippet("Crab.", &["crab".to_string()], 3), "Crab.");
+    }
+
+    #[tokio::test]
+    async fn test_fielded_queries() {
+        let db = MemoryStorage::new();
+        // Each word with its score; where it occurs comes from the index
+        let page = |url: &str, words: &[(&str, f64)]| {
+            let document = Document {
+                url: url.to_string(),
+                ..Document::default()
+            };
+            let id = document.get_id();
+            let scores = words
+                .iter()
+                .map(|(word, tf_idf)| {
+                    TfIdfScore::new(word.to_string(), id, url.to_string(), *tf_idf, 1.0)
+                })
+                .collect();
+            db.add_document(document, scores);
+            id
+        };
+        let docs = page(
+            "https://docs.rs/crab/latest/api/",
+            &[("rust", 0.3), ("hermit", 0.1), ("crab", 0.2)],
+        );
+        let blog = page(
+            "https://blog.example.com/rust",
+            &[("rust", 0.5), ("hermit", 0.1), ("crab", 0.1)],
+        );
+        let example = page("https://example.com/API", &[("rust", 0.1)]);
+        let at = |document: ObjectId, word: &str, title: Vec<u32>, body: Vec<u32>| {
+            let mut words = Words::new(document, word.to_string(), 1);
+            words.positions.title = title;
+            words.positions.body = body;
+            words
+        };
+        let (_dir, index) = index(vec![
+            at(docs, "rust", vec![2], vec![]),
+            at(docs, "hermit", vec![0], vec![]),
+            at(docs, "crab", vec![1], vec![7, 9]),
+            at(blog, "rust", vec![], vec![1, 3, 8]),
+            at(blog, "crab", vec![0], vec![5]),
+            at(blog, "hermit", vec![], vec![4]),
+            at(example, "rust", vec![], vec![0]),
+        ]);
+        let ids = |query: &str| {
+            let query = parse(query).unwrap();
+            let (db, index) = (&db, &index);
+            async move {
+                let found = search(db, index, &query, 0, 10).await.unwrap();
+                found
+                    .results
+                    .iter()
+                    .map(|result| result.document._id)
+                    .collect::<Vec<_>>()
+            }
+        };
+        let none = Vec::<ObjectId>::new();
+
+        assert_eq!(ids("title:rust").await, [docs]);
+        assert_eq!(ids("body:rust").await, [blog, example]);
+        assert_eq!(ids("title:(crab OR rust)").await, [docs, blog]);
+        assert_eq!(ids("title:\"hermit crab\"").await, [docs]);
+        assert_eq!(ids("body:\"hermit crab\"").await, [blog]);
+        assert_eq!(ids("\"hermit crab\"").await, [docs, blog]);
+
+        // Subdomains count as the site, other hosts ending the same do not
+        assert_eq!(ids("rust site:example.com").await, [blog, example]);
+        assert_eq!(ids("rust site:ample.com").await, none);
+        assert_eq!(ids("rust -site:docs.rs").await, [blog, example]);
+        assert_eq!(ids("rust inurl:api").await, [docs, example]);
+        assert_eq!(ids("title:rust OR crab site:docs.rs").await, [docs]);
+        assert_eq!(ids("site:docs.rs").await, none);
+    }
+
+    #[tokio::test]
+    async fn test_fields_of_scores_from_before_schema_version_3() {
+        let db = MemoryStorage::new();
+        let document = Document {
+            url: "https://example.com/crab".to_string(),
+            ..Document::default()
+        };
+        let id = document.get_id();
+        // Scores used to carry per-field counts, all zero when computed
+        // before fields were counted
+        let old = mongodb::bson::doc! {
+            "_id": ObjectId::new(),
+            "word": "crab",
+            "document_id": id,
+            "url": &document.url,
+            "tf": 1.0,
+            "idf": 1.0,
+            "tf_idf": 1.0,
+            "fields": { "title": 0, "description": 0, "body": 0 },
+        };
+        let score: TfIdfScore = mongodb::bson::from_document(old).unwrap();
+        db.add_document(document, vec![score]);
+        let mut crab = Words::new(id, "crab".to_string(), 50);
+        crab.positions.title = vec![0];
+        let (_dir, index) = index(vec![crab]);
+
+        let title = search(&db, &index, &parse("title:crab").unwrap(), 0, 10)
+            .await
+            .unwrap();
+        assert_eq!(title.total, 1);
+        let body = search(&db, &index, &parse("body:crab").unwrap(), 0, 10)
+            .await
+            .unwrap();
+        assert_eq!(body.total, 0);
+    }
+
+    #[tokio::test]
+    async fn test_removed_pages_are_not_counted() {
+        let db = MemoryStorage::new();
+        let (_dir, index) = index(vec![]);
+        let kept = add(&db, "https://example.com/kept", &[("crab", 0.1)]);
+        // A page removed after the TF-IDF job ran, scoring above the other
+        let removed = ObjectId::new();
+        let url = "https://example.com/removed".to_string();
+        let score = TfIdfScore::new("crab".to_string(), removed, url, 0.9, 1.0);
+        db.add_document(Document::default(), vec![score]);
+
+        let found = search(&db, &index, &parse("crab").unwrap(), 0, 1)
+            .await
+            .unwrap();
+        assert_eq!(found.total, 1);
+        assert_eq!(found.results.len(), 1);
+        assert_eq!(found.results[0].document._id, kept);
+        let next = search(&db, &index, &parse("crab").unwrap(), 1, 1)
+            .await
+            .unwrap();
+        assert_eq!(next.total, 1);
+        assert!(next.results.is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+use common::{
+    models::{Document, DocumentMetadata, TfIdfScore},
+    segment::SegmentError,
+};
+use mongodb::bson::{self, doc, oid::ObjectId};
+
+use crate::{db::Database, embedded::EmbeddedStorage, memory::MemoryStorage};
+
+#[derive(Debug)]
+pub enum StorageError {
+    Mongo(mongodb::error::Error),
+    // Anything that went wrong in the embedded or in-memory backend
+    Embedded(String),
+    // The index segments could not be read
+    Index(SegmentError),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Mongo(e) => write!(f, "{}", e),
+            StorageError::Embedded(e) => write!(f, "{}", e),
+            StorageError::Index(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<mongodb::error::Error> for StorageError {
+    fn from(e: mongodb::error::Error) -> Self {
+        StorageError::Mongo(e)
+    }
+}
+
+impl From<SegmentError> for StorageError {
+    fn from(e: SegmentError) -> Self {
+        StorageError::Index(e)
+    }
+}
+
+macro_rules! embedded_error {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for StorageError {
+                fn from(e: $error) -> Self {
+                    StorageError::Embedded(e.to_string())
+                }
+            }
+        )*
+    };
+}
+
+embedded_error!(
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError,
+    bson::de::Error
+);
+
+// Everything the query engine reads from the database
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // Scores of every document containing `word`
+    async fn scores_for(&self, word: &str) -> Result<Vec<TfIdfScore>, StorageError>;
+
+    // Documents with the given ids, in no particular order. Ids without a
+    // document are left out.
+    async fn documents_metadata(
+        &self,
+        ids: &[ObjectId],
+    ) -> Result<Vec<DocumentMetadata>, StorageError>;
+
+    // `summary_text` of the documents with the given ids, for snippets
+    async fn summaries(&self, ids: &[ObjectId]) -> Result<Vec<(ObjectId, String)>, StorageError>;
+
+    async fn find_document(&self, id: ObjectId) -> Result<Option<Document>, StorageError>;
+}
+
+// Opens the backend named by STORAGE_BACKEND, the same way the indexer does
+pub async fn open_storage() -> Arc<dyn Storage> {
+    let backend = dotenv::var("STORAGE_BACKEND").unwrap_or_else(|_| "mongo".to_string());
+    match backend.trim() {
+        "embedded" => {
+            let path =
+                dotenv::var("STORAGE_PATH").unwrap_or_else(|_| "../search_engine.redb".to_string());
+            match EmbeddedStorage::open(&path) {
+                Ok(storage) => {
+                    println!("Opened embedded storage at {}", path);
+                    Arc::new(storage)
+                }
+                Err(e) => {
+                    panic!("{}", e);
+                }
+            }
+        }
+        "memory" => Arc::new(MemoryStorage::new()),
+        "mongo" => Arc::new(Database::new().await),
+        other => {
+            panic!("Unknown STORAGE_BACKEND: {}", other);
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for Database {
+    async fn scores_for(&self, word: &str) -> Result<Vec<TfIdfScore>, StorageError> {
+        Ok(Database::scores_for(self, word).await?)
+    }
+
+    async fn documents_metadata(
+        &self,
+        ids: &[ObjectId],
+    ) -> Result<Vec<DocumentMetadata>, StorageError> {
+        Ok(Database::documents_metadata(self, ids).await?)
+    }
+
+    async fn summaries(&self, ids: &[ObjectId]) -> Result<Vec<(ObjectId, String)>, StorageError> {
+        Ok(Database::summaries(self, ids).await?)
+    }
+
+    async fn find_document(&self, id: ObjectId) -> Result<Option<Document>, StorageError> {
+        Ok(self
+            .collections
+            .documents
+            .find_one(doc! { "_id": id })
+            .await?)
+    }
+}